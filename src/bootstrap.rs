@@ -0,0 +1,69 @@
+use std::fs;
+
+use crate::compile;
+
+/// Checks that the compiler reproduces itself: `compiler_path` compiles
+/// `source` into stage 2, stage 2 compiles it into stage 3, and both stages
+/// must be byte-identical. Otherwise the first differing instruction is
+/// returned as the error.
+pub fn verify_bootstrap(compiler_path: &str, source: &str) -> Result<(), String> {
+    let dir = std::env::temp_dir();
+    let id = std::process::id();
+    let stage2 = dir.join(format!("lum_stage2_{}.l", id));
+    let stage3 = dir.join(format!("lum_stage3_{}.l", id));
+    let stage2 = stage2.to_string_lossy().to_string();
+    let stage3 = stage3.to_string_lossy().to_string();
+
+    let res = compile(compiler_path, source, &[], &stage2)
+        .map_err(|e| format!("building stage 2 failed: {}", e))
+        .and_then(|_| {
+            compile(&stage2, source, &[], &stage3)
+                .map_err(|e| format!("building stage 3 failed: {}", e))
+        })
+        .and_then(|_| compare(&stage2, &stage3));
+
+    let _ = fs::remove_file(stage2);
+    let _ = fs::remove_file(stage3);
+    res
+}
+
+fn compare(stage2: &str, stage3: &str) -> Result<(), String> {
+    let file2 = fs::read_to_string(stage2).map_err(|e| e.to_string())?;
+    let file3 = fs::read_to_string(stage3).map_err(|e| e.to_string())?;
+    if file2 == file3 {
+        return Ok(());
+    }
+
+    let (strings2, code2) = split(&file2);
+    let (strings3, code3) = split(&file3);
+    if let Some(i) = first_difference(&strings2, &strings3) {
+        return Err(format!(
+            "string {} differs:\n  stage 2: {}\n  stage 3: {}",
+            i,
+            strings2.get(i).unwrap_or(&"<missing>"),
+            strings3.get(i).unwrap_or(&"<missing>")
+        ));
+    }
+    match first_difference(&code2, &code3) {
+        Some(i) => Err(format!(
+            "instruction {} differs:\n  stage 2: {}\n  stage 3: {}",
+            i,
+            code2.get(i).unwrap_or(&"<missing>"),
+            code3.get(i).unwrap_or(&"<missing>")
+        )),
+        // Only whitespace at the end of the file differs.
+        None => Err("stages differ in trailing whitespace".to_string()),
+    }
+}
+
+/// Splits a bytecode file into its string table and its instructions.
+fn split(file: &str) -> (Vec<&str>, Vec<&str>) {
+    let mut lines = file.lines();
+    let count = lines.next().and_then(|c| c.parse().ok()).unwrap_or(0);
+    let strings = lines.by_ref().take(count).collect();
+    (strings, lines.collect())
+}
+
+fn first_difference(a: &[&str], b: &[&str]) -> Option<usize> {
+    (0..a.len().max(b.len())).find(|&i| a.get(i) != b.get(i))
+}
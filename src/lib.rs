@@ -1,54 +1,283 @@
 use std::{
     fs,
     io::{stdout, Write},
+    panic,
+    path::{Path, PathBuf},
     str::FromStr,
-    time::Instant,
 };
 
 use opcode::OpCode;
 use vm::Vm;
 
+mod bootstrap;
+mod linker;
 mod opcode;
+mod optimizer;
+mod testing;
 mod vm;
 
+pub use bootstrap::verify_bootstrap;
+pub use linker::link_files;
+pub use testing::run_tests;
+
 pub fn run_bytecode(bytecode_path: &str, strings_path: &str) {
     let code_from_file: Vec<OpCode> = fs::read_to_string(bytecode_path)
         .unwrap()
         .lines()
-        .into_iter()
         .map(|x| OpCode::from_str(x).unwrap())
         .collect();
 
     let strings: Vec<String> = fs::read_to_string(strings_path)
         .unwrap()
         .lines()
-        .into_iter()
-        .map(|x| x.to_string())
+        .map(unescape)
         .collect();
 
-    let mut vm = Vm::new(code_from_file, strings);
+    let mut vm = Vm::new(optimizer::optimize(code_from_file), strings);
     vm.run(&mut stdout());
     println!("done running bytecode")
 }
 
-pub fn run_with_compiler(compiler_path: &str) {
-    let (code, strings) = read_bytecode(compiler_path);
-    let mut vm = Vm::new(code, strings);
-    vm.run(&mut stdout());
-    
-    let (code, strings) = read_bytecode("test.l");
-    let mut vm = Vm::new(code, strings);
-    vm.run(&mut stdout());
+/// Compiles `file` and runs it with `args` as its `#args`. Imports are also
+/// looked up in the `includes` directories. A linked `.l` file is run as it
+/// is. Compiler warnings are printed before the program runs, compile errors
+/// as the compiler reports them and runtime errors as `error: ...` instead of
+/// a panic. Returns the exit code for the process.
+pub fn run_file(compiler_path: &str, file: &str, includes: &[String], args: Vec<String>) -> i32 {
+    let out_path = std::env::temp_dir().join(format!("lum_run_{}.l", std::process::id()));
+    let out_path = out_path.to_string_lossy().to_string();
+
+    // Compile and runtime errors are printed below, not by the hook.
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let compiled = if file.ends_with(".l") {
+        Ok(file.to_string())
+    } else {
+        let mut flags = cache_flags(compiler_path);
+        for dir in includes {
+            flags.extend(["--include".to_string(), dir.clone()]);
+        }
+        compile(compiler_path, file, &flags, &out_path).map(|diagnostics| {
+            eprint!("{}", diagnostics);
+            out_path.clone()
+        })
+    };
+    let res = compiled.and_then(|out_path| {
+        read_bytecode(&out_path)
+            .and_then(|(code, strings)| {
+                let mut vm = Vm::new(code, strings).with_args(args);
+                let mut out = stdout();
+                let res = panic::catch_unwind(panic::AssertUnwindSafe(|| vm.run(&mut out)));
+                out.flush().unwrap();
+                res.map_err(|e| testing::panic_message(&e))
+            })
+            .map_err(|e| format!("error: {}", e))
+    });
+    panic::set_hook(hook);
+    let _ = fs::remove_file(&out_path);
+
+    match res {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            101
+        }
+    }
+}
 
+/// Compiles every module of `file` into an object file in `obj_dir`, for
+/// `lum link`. Returns the exit code for the process.
+pub fn compile_file(compiler_path: &str, file: &str, obj_dir: &str) -> i32 {
+    let mut args = vec![
+        file.to_string(),
+        "--obj-dir".to_string(),
+        obj_dir.to_string(),
+    ];
+    args.extend(cache_flags(compiler_path));
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let res = run_compiler(compiler_path, args);
+    panic::set_hook(hook);
+    match res {
+        Ok(diagnostics) => {
+            eprint!("{}", diagnostics);
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            101
+        }
+    }
+}
+
+/// Compiles `file` with the given flags and links its modules into the
+/// executable `out_path`. Returns the warnings of the compiler.
+fn compile(
+    compiler_path: &str,
+    file: &str,
+    flags: &[String],
+    out_path: &str,
+) -> Result<String, String> {
+    let obj_dir = format!("{}.objects", out_path);
+    let mut args = vec![file.to_string()];
+    args.extend(flags.iter().cloned());
+    args.extend(["--obj-dir".to_string(), obj_dir.clone()]);
+    let res = run_compiler(compiler_path, args).and_then(|diagnostics| {
+        link_files(std::slice::from_ref(&obj_dir), out_path)
+            .map(|_| diagnostics)
+            .map_err(|e| format!("error: could not link: {}", e))
+    });
+    let _ = fs::remove_dir_all(&obj_dir);
+    res
+}
+
+/// The flags that let the compiler use the build cache in `LUM_CACHE`, or
+/// `.lum-cache` if it is not set. The version of the compiler is the hash of
+/// its bytecode, so a changed compiler never uses the objects of another one.
+fn cache_flags(compiler_path: &str) -> Vec<String> {
+    let dir = std::env::var("LUM_CACHE").unwrap_or_else(|_| ".lum-cache".to_string());
+    let version = vm::hash(&fs::read(compiler_path).unwrap_or_default());
+    vec![
+        "--cache".to_string(),
+        dir,
+        "--compiler-version".to_string(),
+        version,
+    ]
+}
+
+/// The directory lum is installed in, the first directory above the running
+/// executable that has the standard library in `lum/std`. It is relative if
+/// it is inside the current directory, and falls back to the current
+/// directory.
+pub fn home_dir() -> PathBuf {
+    let home = std::env::current_exe().ok().and_then(|exe| {
+        exe.ancestors()
+            .find(|dir| dir.join("lum/std").is_dir())
+            .map(Path::to_path_buf)
+    });
+    let Some(home) = home else {
+        return PathBuf::new();
+    };
+    let cwd = std::env::current_dir().unwrap_or_default();
+    match home.strip_prefix(&cwd) {
+        Ok(relative) => relative.to_path_buf(),
+        Err(_) => home,
+    }
+}
+
+/// The flags that point the compiler to the standard library of this lum
+/// installation, so imports of it work from any directory.
+fn std_flags() -> Vec<String> {
+    let std = home_dir().join("lum/std");
+    vec!["--std".to_string(), std.to_string_lossy().to_string()]
+}
+
+/// Runs the compiler with `args` and returns its warnings. If it fails, the
+/// error is what the compiler printed, its diagnostics, followed by
+/// `error: <why>`.
+fn run_compiler(compiler_path: &str, mut args: Vec<String>) -> Result<String, String> {
+    args.extend(std_flags());
+    let (code, strings) = read_bytecode(compiler_path).map_err(|e| format!("error: {}", e))?;
+    let mut vm = Vm::new(code, strings).with_args(args);
+    let mut out = vec![];
+    let res = panic::catch_unwind(panic::AssertUnwindSafe(|| vm.run(&mut out)));
+    let printed = String::from_utf8_lossy(&out).to_string();
+    res.map(|_| printed.clone()).map_err(|e| {
+        // The compiler stops with `#err`, whose message starts with `err: `.
+        let message = testing::panic_message(&e);
+        let message = message.strip_prefix("err: ").unwrap_or(&message);
+        format!("{}error: {}", printed, message)
+    })
+}
+
+/// Compiles the compiler with `compiler_path` into `test.l`, then lets
+/// `test.l` compile it again.
+pub fn run_with_compiler(compiler_path: &str) {
+    build(compiler_path);
+    build("test.l");
     println!("done...")
 }
 
-fn read_bytecode(path: &str) -> (Vec<OpCode>, Vec<String>) {
-    let file = fs::read_to_string(path).unwrap();
+/// Runs the compiler with the arguments of the process and links the result
+/// into `test.l`.
+fn build(compiler_path: &str) {
+    let obj_dir = std::env::temp_dir().join(format!("lum_build_{}", std::process::id()));
+    let obj_dir = obj_dir.to_string_lossy().to_string();
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.iter().any(|arg| arg == "--std") {
+        args.extend(std_flags());
+    }
+    args.extend(["--obj-dir".to_string(), obj_dir.clone()]);
+    args.extend(cache_flags(compiler_path));
+
+    let (code, strings) = read_bytecode(compiler_path).unwrap_or_else(|e| panic!("{}", e));
+    let mut vm = Vm::new(code, strings).with_args(args);
+    vm.run(&mut stdout());
+    let res = link_files(std::slice::from_ref(&obj_dir), "test.l");
+    let _ = fs::remove_dir_all(&obj_dir);
+    if let Err(e) = res {
+        panic!("link error: {}", e);
+    }
+}
+
+/// Reads a linked executable. The code is optimized as it is loaded, the
+/// file itself only holds the instructions the compiler emitted.
+fn read_bytecode(path: &str) -> Result<(Vec<OpCode>, Vec<String>), String> {
+    let file = fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path, e))?;
+    let invalid = |what: String| format!("{} is not a lum executable: {}", path, what);
     let mut lines = file.lines();
-    let string_count: usize = lines.next().unwrap().parse().unwrap();
-    let strings: Vec<String> = lines.by_ref().take(string_count).map(|l| l.to_string()).collect();
-    let code = lines.map(|l| OpCode::from_str(l).unwrap()).collect();
-    (code, strings)
+    let string_count: usize = lines
+        .next()
+        .and_then(|l| l.parse().ok())
+        .ok_or_else(|| invalid("missing string count".to_string()))?;
+    let strings: Vec<String> = lines.by_ref().take(string_count).map(unescape).collect();
+    if strings.len() != string_count {
+        return Err(invalid("missing strings".to_string()));
+    }
+    let code = lines
+        .map(|l| OpCode::from_str(l).map_err(|_| invalid(format!("invalid instruction {}", l))))
+        .collect::<Result<_, _>>()?;
+    Ok((optimizer::optimize(code), strings))
+}
+
+/// String constants are stored the way they were written in the source, so
+/// escape sequences are decoded when the string table is loaded.
+fn unescape(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => res.push('\n'),
+            Some('r') => res.push('\r'),
+            Some('t') => res.push('\t'),
+            Some('\\') => res.push('\\'),
+            Some('"') => res.push('"'),
+            Some('0') => res.push('\0'),
+            Some('u') => match unicode_escape(&mut chars) {
+                Some(c) => res.push(c),
+                None => res.push_str("\\u"),
+            },
+            Some(other) => {
+                res.push('\\');
+                res.push(other);
+            }
+            None => res.push('\\'),
+        }
+    }
+    res
+}
 
+/// Decodes the `{XXXX}` part of a `\u{XXXX}` escape. The iterator is only
+/// advanced if the escape is valid.
+fn unicode_escape(chars: &mut std::str::Chars) -> Option<char> {
+    let rest = chars.as_str();
+    let end = rest.find('}')?;
+    let hex = rest.strip_prefix('{')?.get(..end - 1)?;
+    let c = u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)?;
+    *chars = rest[end + 1..].chars();
+    Some(c)
 }
@@ -0,0 +1,325 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::opcode::MethodInfo;
+
+/// A class from the symbol table of an object file. Method starts are
+/// relative to the start of the module's code. `statics` names the methods
+/// that belong to the class object instead of its instances.
+struct Class {
+    name: String,
+    fields: Vec<String>,
+    methods: Vec<(String, MethodInfo)>,
+    static_fields: Vec<String>,
+    statics: Vec<String>,
+}
+
+/// The compiled code of one module, as written by the compiler with
+/// `--obj-dir`.
+struct Object {
+    module: String,
+    imports: Vec<String>,
+    classes: Vec<Class>,
+    enums: Vec<String>,
+    strings: Vec<String>,
+    /// The number of global variables of the module.
+    globals: usize,
+    code: Vec<String>,
+}
+
+/// Links the object files in `paths` into the executable `out_path`. A
+/// directory stands for all `.lo` files in it.
+pub fn link_files(paths: &[String], out_path: &str) -> Result<(), String> {
+    let mut files = vec![];
+    for path in paths {
+        let path = Path::new(path);
+        if path.is_dir() {
+            let mut entries = fs::read_dir(path)
+                .map_err(|e| format!("could not read {}: {}", path.display(), e))?
+                .map(|entry| entry.map(|e| e.path()))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| e.to_string())?;
+            entries.retain(|p| p.extension().is_some_and(|e| e == "lo"));
+            entries.sort();
+            files.extend(entries);
+        } else {
+            files.push(path.to_path_buf());
+        }
+    }
+
+    let mut objects = vec![];
+    for file in &files {
+        let text = fs::read_to_string(file)
+            .map_err(|e| format!("could not read {}: {}", file.display(), e))?;
+        objects.push(parse(&text).map_err(|e| format!("{}: {}", file.display(), e))?);
+    }
+    let executable = link(&objects)?;
+    fs::write(out_path, executable).map_err(|e| format!("could not write {}: {}", out_path, e))
+}
+
+/// Puts the code of every module after the code of the modules it imports,
+/// and renumbers jumps, strings, globals and method starts to match. Every
+/// class object gets a slot, in the order the classes are first used.
+fn link(objects: &[Object]) -> Result<String, String> {
+    let order = order(objects)?;
+
+    let mut strings: Vec<&str> = vec![];
+    let mut code: Vec<String> = vec![];
+    let mut globals = 0;
+    let mut classes = HashMap::new();
+    // Where the code of each module starts in the executable.
+    let mut bases: HashMap<&str, (usize, &Object)> = HashMap::new();
+    for object in order {
+        let base = code.len();
+        let string_base = strings.len();
+        bases.insert(&object.module, (base, object));
+        strings.extend(object.strings.iter().map(String::as_str));
+        for line in &object.code {
+            code.push(relocate(
+                line,
+                base,
+                string_base,
+                globals,
+                &bases,
+                &mut classes,
+            )?);
+        }
+        globals += object.globals;
+    }
+
+    let mut res = vec![strings.len().to_string()];
+    res.extend(strings.iter().map(|s| s.to_string()));
+    res.extend(code);
+    Ok(res.join("\n"))
+}
+
+/// Every module comes after the ones it imports. Modules that are not
+/// imported by another one keep the order they were given in.
+fn order(objects: &[Object]) -> Result<Vec<&Object>, String> {
+    fn visit<'a>(
+        object: &'a Object,
+        by_module: &HashMap<&str, &'a Object>,
+        visiting: &mut Vec<&'a str>,
+        res: &mut Vec<&'a Object>,
+    ) -> Result<(), String> {
+        if res.iter().any(|o| o.module == object.module) {
+            return Ok(());
+        }
+        if visiting.contains(&object.module.as_str()) {
+            return Err(format!("cyclic import of {}", object.module));
+        }
+        visiting.push(&object.module);
+        for import in &object.imports {
+            match by_module.get(import.as_str()) {
+                Some(imported) => visit(imported, by_module, visiting, res)?,
+                None => {
+                    return Err(format!(
+                        "{} imports {}, which is not linked",
+                        object.module, import
+                    ))
+                }
+            }
+        }
+        visiting.pop();
+        res.push(object);
+        Ok(())
+    }
+
+    let mut by_module = HashMap::new();
+    for object in objects {
+        if by_module.insert(object.module.as_str(), object).is_some() {
+            return Err(format!("{} is linked twice", object.module));
+        }
+    }
+    let mut res = vec![];
+    for object in objects {
+        visit(object, &by_module, &mut vec![], &mut res)?;
+    }
+    Ok(res)
+}
+
+fn relocate(
+    line: &str,
+    base: usize,
+    string_base: usize,
+    global_base: usize,
+    bases: &HashMap<&str, (usize, &Object)>,
+    classes: &mut HashMap<String, usize>,
+) -> Result<String, String> {
+    let mut parts = line.split('|');
+    let op = parts.next().unwrap_or_default();
+    let offset = |n: Option<&str>, by: usize| -> Result<usize, String> {
+        n.and_then(|n| n.parse::<usize>().ok())
+            .map(|n| n + by)
+            .ok_or_else(|| format!("invalid instruction {}", line))
+    };
+    match op {
+        "Jump" | "JumpIfFalse" => Ok(format!("{}|{}", op, offset(parts.next(), base)?)),
+        "PushString" => Ok(format!("{}|{}", op, offset(parts.next(), string_base)?)),
+        "GetGlobal" | "SetGlobal" => Ok(format!("{}|{}", op, offset(parts.next(), global_base)?)),
+        "InstanceOf" | "ClassOf" | "GetClass" | "SetClass" => {
+            let module = parts.next().unwrap_or_default();
+            let name = parts.next().unwrap_or_default();
+            let (base, object) = bases
+                .get(module)
+                .ok_or_else(|| format!("class {} of {} is not linked", name, module))?;
+            let mut slot = || {
+                let count = classes.len();
+                *classes
+                    .entry(format!("{}|{}", module, name))
+                    .or_insert(count)
+            };
+            if matches!(op, "GetClass" | "SetClass") && object.enums.iter().any(|e| e == name) {
+                return Ok(format!("{}|{}", op, slot()));
+            }
+            let class = object
+                .classes
+                .iter()
+                .find(|c| c.name == name)
+                .ok_or_else(|| format!("{} has no class '{}'", module, name))?;
+            let methods = |statics: bool| {
+                class
+                    .methods
+                    .iter()
+                    .filter(move |(n, _)| class.statics.contains(n) == statics)
+            };
+            match op {
+                "InstanceOf" => {
+                    let unset = parts
+                        .next()
+                        .ok_or_else(|| format!("invalid instruction {}", line))?;
+                    Ok(instance(&class.fields, methods(false), *base, unset))
+                }
+                "ClassOf" => Ok(instance(&class.static_fields, methods(true), *base, "")),
+                _ => Ok(format!("{}|{}", op, slot())),
+            }
+        }
+        _ => Ok(line.to_string()),
+    }
+}
+
+/// The `Instance` instruction for a class, or for the object that holds its
+/// static fields and methods, whose module starts at `base`. Fields are
+/// listed in reverse, the order their values are popped. `unset` are the
+/// fields that were left out, separated by commas.
+fn instance<'a>(
+    fields: &[String],
+    methods: impl Iterator<Item = &'a (String, MethodInfo)>,
+    base: usize,
+    unset: &str,
+) -> String {
+    let methods: Vec<_> = methods.collect();
+    let join = |items: Vec<String>| items.join(",");
+    let fields = join(fields.iter().rev().cloned().collect());
+    let names = join(methods.iter().map(|(n, _)| n.clone()).collect());
+    let starts = join(
+        methods
+            .iter()
+            .map(|(_, m)| (m.start + base).to_string())
+            .collect(),
+    );
+    let arities = join(methods.iter().map(|(_, m)| m.arity.to_string()).collect());
+    let required = join(
+        methods
+            .iter()
+            .map(|(_, m)| m.required.to_string())
+            .collect(),
+    );
+    format!(
+        "Instance|{}|{}|{}|{}|{}|{}",
+        fields, names, starts, arities, required, unset
+    )
+}
+
+fn parse(text: &str) -> Result<Object, String> {
+    let mut lines = text.lines();
+    let mut object = Object {
+        module: String::new(),
+        imports: vec![],
+        classes: vec![],
+        enums: vec![],
+        strings: vec![],
+        globals: 0,
+        code: vec![],
+    };
+    let count = |lines: &mut std::str::Lines, n: &str| -> Result<Vec<String>, String> {
+        let n: usize = n.parse().map_err(|_| format!("invalid count {}", n))?;
+        let res: Vec<String> = lines.by_ref().take(n).map(String::from).collect();
+        if res.len() != n {
+            return Err("unexpected end of file".to_string());
+        }
+        Ok(res)
+    };
+    while let Some(line) = lines.next() {
+        let (kind, rest) = line.split_once('|').unwrap_or((line, ""));
+        match kind {
+            "module" => object.module = rest.to_string(),
+            "import" => object.imports.push(rest.to_string()),
+            "class" => object.classes.push(parse_class(rest)?),
+            // Variants are compiled to constants, only the list of their
+            // names is stored in the slot of the enum.
+            "enum" => {
+                let name = rest.split('|').next().unwrap_or_default();
+                object.enums.push(name.to_string());
+            }
+            "strings" => object.strings = count(&mut lines, rest)?,
+            "globals" => {
+                object.globals = rest
+                    .parse()
+                    .map_err(|_| format!("invalid count {}", rest))?
+            }
+            "code" => object.code = count(&mut lines, rest)?,
+            _ => return Err(format!("unexpected line {}", line)),
+        }
+    }
+    Ok(object)
+}
+
+/// `<name>|<fields>|<method names>|<starts>|<arities>|<required>|<static fields>|<static methods>|<required fields>`,
+/// the number of required fields is only used by the compiler.
+fn parse_class(line: &str) -> Result<Class, String> {
+    let invalid = || format!("invalid class {}", line);
+    let mut parts = line.split('|');
+    let name = parts.next().ok_or_else(invalid)?.to_string();
+    let mut list = || -> Result<Vec<&str>, String> {
+        let part = parts.next().ok_or_else(invalid)?;
+        Ok(part.split(',').filter(|x| !x.is_empty()).collect())
+    };
+    let fields = list()?.into_iter().map(String::from).collect();
+    let names = list()?;
+    let numbers = |items: Vec<&str>| -> Result<Vec<usize>, String> {
+        items
+            .iter()
+            .map(|x| x.parse().map_err(|_| invalid()))
+            .collect()
+    };
+    let starts = numbers(list()?)?;
+    let arities = numbers(list()?)?;
+    let required = numbers(list()?)?;
+    let static_fields = list()?.into_iter().map(String::from).collect();
+    let statics = list()?.into_iter().map(String::from).collect();
+    let required_fields = parts.next().and_then(|n| n.parse::<usize>().ok());
+    if required_fields.is_none() || parts.next().is_some() {
+        return Err(invalid());
+    }
+    if starts.len() != names.len() || arities.len() != names.len() || required.len() != names.len()
+    {
+        return Err(invalid());
+    }
+    let methods = (0..names.len())
+        .map(|i| {
+            let info = MethodInfo {
+                start: starts[i],
+                arity: arities[i],
+                required: required[i],
+            };
+            (names[i].to_string(), info)
+        })
+        .collect();
+    Ok(Class {
+        name,
+        fields,
+        methods,
+        static_fields,
+        statics,
+    })
+}
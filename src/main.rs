@@ -1,5 +1,70 @@
-use lum::run_with_compiler;
+use lum::{
+    compile_file, home_dir, link_files, run_file, run_tests, run_with_compiler, verify_bootstrap,
+};
 
 fn main() {
-    run_with_compiler("compilers/2.l");
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let compiler = home_dir().join("compilers/2.l");
+    let compiler = compiler.to_string_lossy();
+    match args.first().map(String::as_str) {
+        Some("test") => {
+            let paths = match args.len() {
+                1 => vec![".".to_string()],
+                _ => args[1..].to_vec(),
+            };
+            std::process::exit(run_tests(&compiler, &paths));
+        }
+        Some("run") => {
+            let mut includes = vec![];
+            let mut rest = &args[1..];
+            while rest.len() > 1 && rest[0] == "--include" {
+                includes.push(rest[1].clone());
+                rest = &rest[2..];
+            }
+            match rest.first() {
+                Some(file) => {
+                    std::process::exit(run_file(&compiler, file, &includes, rest[1..].to_vec()))
+                }
+                None => {
+                    eprintln!("usage: lum run [--include <dir>]... <file.lum | file.l> [args...]");
+                    std::process::exit(2);
+                }
+            }
+        }
+        Some("compile") => match (args.get(1), args.get(2)) {
+            (Some(file), Some(obj_dir)) => {
+                std::process::exit(compile_file(&compiler, file, obj_dir))
+            }
+            _ => {
+                eprintln!("usage: lum compile <file> <obj-dir>");
+                std::process::exit(2);
+            }
+        },
+        Some("link") => match args.get(1).map(String::as_str) {
+            Some("-o") if args.len() > 3 => {
+                if let Err(e) = link_files(&args[3..], &args[2]) {
+                    eprintln!("error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+            _ => {
+                eprintln!("usage: lum link -o <out> <objects or dirs...>");
+                std::process::exit(2);
+            }
+        },
+        Some("bootstrap") => {
+            if args.get(1).map(String::as_str) != Some("--verify") {
+                eprintln!("usage: lum bootstrap --verify");
+                std::process::exit(2);
+            }
+            match verify_bootstrap(&compiler, "lum/compiler/main.lum") {
+                Ok(()) => println!("bootstrap verified: stage 2 and stage 3 are identical"),
+                Err(e) => {
+                    println!("bootstrap verification failed: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        _ => run_with_compiler(&compiler),
+    }
 }
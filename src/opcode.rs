@@ -1,6 +1,15 @@
 use std::str::FromStr;
 
-#[derive(Debug)]
+/// Where a method starts and how many arguments it takes, not counting the
+/// receiver. Parameters after the first `required` ones have defaults.
+#[derive(Debug, Clone, Copy)]
+pub struct MethodInfo {
+    pub start: usize,
+    pub arity: usize,
+    pub required: usize,
+}
+
+#[derive(Debug, Clone)]
 pub enum OpCode {
     PushInt(i32),
     PushBool(bool),
@@ -9,15 +18,23 @@ pub enum OpCode {
     JumpIfFalse(usize),
     SetLocal(usize),
     GetLocal(usize),
+    SetGlobal(usize),
+    GetGlobal(usize),
+    SetClass(usize),
+    GetClass(usize),
     Jump(usize),
     Plus,
     Minus,
+    Multiply,
+    Divide,
     Native(usize),
     PushString(usize),
     List(usize),
     GetField(String),
     SetField(String),
-    Instance(Vec<String>, Vec<String>, Vec<usize>),
+    /// Fields, methods and the fields that were not given, which `init`
+    /// sets to their defaults.
+    Instance(Vec<String>, Vec<(String, MethodInfo)>, Vec<String>),
     Get(String),
     Set(String),
     Call(String, usize),
@@ -37,6 +54,18 @@ pub enum OpCode {
     IndexSet,
     PushSelf,
     Print(usize),
+    /// Pushes whether the argument at this index was left out by the caller.
+    MissingArg(usize),
+    /// Pushes whether the field was left out when the instance was created.
+    MissingField(String),
+    // Superinstructions, only created by the optimizer and never written to
+    // a file.
+    /// `GetLocal n; PushInt k; Plus; SetLocal n`
+    AddLocal(usize, i32),
+    /// `Less; JumpIfFalse p`
+    JumpIfNotLess(usize),
+    /// `Equals; JumpIfFalse p`
+    JumpIfNotEqual(usize),
 }
 
 impl OpCode {
@@ -54,6 +83,8 @@ impl FromStr for OpCode {
             Some("PushNil") => Ok(OpCode::PushNil),
             Some("Plus") => Ok(OpCode::Plus),
             Some("Minus") => Ok(OpCode::Minus),
+            Some("Multiply") => Ok(OpCode::Multiply),
+            Some("Divide") => Ok(OpCode::Divide),
             Some("Return") => Ok(OpCode::Return),
             Some("Pop") => Ok(OpCode::Pop),
             Some("Neg") => Ok(OpCode::Neg),
@@ -70,53 +101,75 @@ impl FromStr for OpCode {
             Some("IndexSet") => Ok(OpCode::IndexSet),
             Some("PushSelf") => Ok(OpCode::PushSelf),
             // 1 param
-            Some("JumpIfFalse") => Ok(OpCode::JumpIfFalse(split.next().unwrap().parse().unwrap())),
-            Some("SetLocal") => Ok(OpCode::SetLocal(split.next().unwrap().parse().unwrap())),
-            Some("GetLocal") => Ok(OpCode::GetLocal(split.next().unwrap().parse().unwrap())),
-            Some("Jump") => Ok(OpCode::Jump(split.next().unwrap().parse().unwrap())),
-            Some("Native") => Ok(OpCode::Native(split.next().unwrap().parse().unwrap())),
-            Some("PushString") => Ok(OpCode::PushString(split.next().unwrap().parse().unwrap())),
-            Some("List") => Ok(OpCode::List(split.next().unwrap().parse().unwrap())),
-            Some("GetField") => Ok(OpCode::GetField(split.next().unwrap().to_string())),
-            Some("SetField") => Ok(OpCode::SetField(split.next().unwrap().to_string())),
-            Some("Get") => Ok(OpCode::Get(split.next().unwrap().to_string())),
-            Some("Set") => Ok(OpCode::Set(split.next().unwrap().to_string())),
-            Some("PushInt") => Ok(OpCode::PushInt(split.next().unwrap().parse().unwrap())),
-            Some("PushBool") => Ok(OpCode::PushBool(split.next().unwrap().parse().unwrap())),
-            Some("PushFloat") => Ok(OpCode::PushFloat(split.next().unwrap().parse().unwrap())),
-            Some("Print") => Ok(OpCode::Print(split.next().unwrap().parse().unwrap())),
+            Some("JumpIfFalse") => Ok(OpCode::JumpIfFalse(arg(&mut split)?)),
+            Some("SetLocal") => Ok(OpCode::SetLocal(arg(&mut split)?)),
+            Some("GetLocal") => Ok(OpCode::GetLocal(arg(&mut split)?)),
+            Some("SetGlobal") => Ok(OpCode::SetGlobal(arg(&mut split)?)),
+            Some("GetGlobal") => Ok(OpCode::GetGlobal(arg(&mut split)?)),
+            Some("SetClass") => Ok(OpCode::SetClass(arg(&mut split)?)),
+            Some("GetClass") => Ok(OpCode::GetClass(arg(&mut split)?)),
+            Some("Jump") => Ok(OpCode::Jump(arg(&mut split)?)),
+            Some("Native") => Ok(OpCode::Native(arg(&mut split)?)),
+            Some("PushString") => Ok(OpCode::PushString(arg(&mut split)?)),
+            Some("List") => Ok(OpCode::List(arg(&mut split)?)),
+            Some("GetField") => Ok(OpCode::GetField(arg(&mut split)?)),
+            Some("SetField") => Ok(OpCode::SetField(arg(&mut split)?)),
+            Some("Get") => Ok(OpCode::Get(arg(&mut split)?)),
+            Some("Set") => Ok(OpCode::Set(arg(&mut split)?)),
+            Some("PushInt") => Ok(OpCode::PushInt(arg(&mut split)?)),
+            Some("PushBool") => Ok(OpCode::PushBool(arg(&mut split)?)),
+            Some("PushFloat") => Ok(OpCode::PushFloat(arg(&mut split)?)),
+            Some("Print") => Ok(OpCode::Print(arg(&mut split)?)),
+            Some("MissingArg") => Ok(OpCode::MissingArg(arg(&mut split)?)),
+            Some("MissingField") => Ok(OpCode::MissingField(arg(&mut split)?)),
             // 2 param
             Some("Call") => {
-                let l1 = split.next().unwrap().to_string();
-                let l2 = split.next().unwrap().parse().unwrap();
+                let l1 = arg(&mut split)?;
+                let l2 = arg(&mut split)?;
                 Ok(OpCode::Call(l1, l2))
             }
-            // 3 param
+            // fields|names|starts|arities|required|unset
             Some("Instance") => {
-                let l1 = split
-                    .next()
-                    .unwrap()
-                    .split(",")
-                    .map(|x| x.to_string())
-                    .filter(|x| x != "")
-                    .collect::<Vec<String>>();
-                let l2 = split
-                    .next()
-                    .unwrap()
-                    .split(",")
-                    .map(|x| x.to_string())
-                    .filter(|x| x != "")
-                    .collect::<Vec<String>>();
-                let l3 = split
-                    .next()
-                    .unwrap()
-                    .split(",")
-                    .filter_map(|x| x.parse().ok())
-                    .collect::<Vec<usize>>();
-                Ok(OpCode::Instance(l1, l2, l3))
+                let fields = list(&mut split)?;
+                let names: Vec<String> = list(&mut split)?;
+                let starts: Vec<usize> = list(&mut split)?;
+                let arities: Vec<usize> = list(&mut split)?;
+                let required: Vec<usize> = list(&mut split)?;
+                let unset = list(&mut split)?;
+                if starts.len() != names.len()
+                    || arities.len() != names.len()
+                    || required.len() != names.len()
+                {
+                    return Err(OpCodeErr {});
+                }
+                let methods = (0..names.len())
+                    .map(|i| {
+                        let info = MethodInfo {
+                            start: starts[i],
+                            arity: arities[i],
+                            required: required[i],
+                        };
+                        (names[i].clone(), info)
+                    })
+                    .collect();
+                Ok(OpCode::Instance(fields, methods, unset))
             }
-            Some(code) => panic!("unexpected opcode {}", code),
-            None => panic!("iterator is empty"),
+            _ => Err(OpCodeErr {}),
         }
     }
 }
+
+/// The next operand of an instruction.
+fn arg<T: FromStr>(split: &mut std::str::Split<&str>) -> Result<T, OpCodeErr> {
+    let part = split.next().ok_or(OpCodeErr {})?;
+    part.parse().map_err(|_| OpCodeErr {})
+}
+
+/// The next operand of an instruction, a comma separated list.
+fn list<T: FromStr>(split: &mut std::str::Split<&str>) -> Result<Vec<T>, OpCodeErr> {
+    let part = split.next().ok_or(OpCodeErr {})?;
+    part.split(",")
+        .filter(|x| !x.is_empty())
+        .map(|x| x.parse().map_err(|_| OpCodeErr {}))
+        .collect()
+}
@@ -0,0 +1,280 @@
+use crate::opcode::OpCode;
+
+/// Runs the peephole optimizer over linked code until nothing changes.
+///
+/// Every pass threads jumps, then rewrites the code in one sweep: code that
+/// can't be reached is dropped, constants are folded and common sequences are
+/// fused into superinstructions. A sequence is only rewritten if nothing
+/// jumps into the middle of it. Jump targets and method starts are renumbered
+/// afterwards, a target that was removed moves to the next kept instruction.
+pub fn optimize(mut code: Vec<OpCode>) -> Vec<OpCode> {
+    loop {
+        let threaded = thread_jumps(&mut code);
+        let len = code.len();
+        code = rewrite(code);
+        if !threaded && code.len() == len {
+            return code;
+        }
+    }
+}
+
+/// Replaces jumps to a `Jump` with a jump to where that one goes.
+fn thread_jumps(code: &mut [OpCode]) -> bool {
+    let mut changed = false;
+    for i in 0..code.len() {
+        let Some(target) = jump_target(&code[i]) else {
+            continue;
+        };
+        let mut new_target = target;
+        let mut seen = vec![i];
+        while let Some(OpCode::Jump(next)) = code.get(new_target) {
+            // A loop of jumps never ends, so it is left alone.
+            if seen.contains(&new_target) {
+                new_target = target;
+                break;
+            }
+            seen.push(new_target);
+            new_target = *next;
+        }
+        if new_target != target {
+            set_jump_target(&mut code[i], new_target);
+            changed = true;
+        }
+    }
+    changed
+}
+
+fn rewrite(code: Vec<OpCode>) -> Vec<OpCode> {
+    let leaders = leaders(&code);
+    let mut new_code = Vec::with_capacity(code.len());
+    // Where each instruction of `code` is in `new_code`.
+    let mut map = vec![0; code.len() + 1];
+    let mut reachable = true;
+    let mut i = 0;
+    while i < code.len() {
+        if leaders[i] {
+            reachable = true;
+        }
+        if !reachable {
+            map[i] = new_code.len();
+            i += 1;
+            continue;
+        }
+        let (consumed, replacement) = peephole(&code, &leaders, i);
+        for m in &mut map[i..i + consumed] {
+            *m = new_code.len();
+        }
+        i += consumed;
+        if let Some(op) = replacement {
+            reachable = !matches!(op, OpCode::Jump(_) | OpCode::Return);
+            new_code.push(op);
+        }
+    }
+    map[code.len()] = new_code.len();
+
+    for op in &mut new_code {
+        if let Some(target) = jump_target(op) {
+            set_jump_target(op, map[target]);
+        }
+        if let OpCode::Instance(_, methods, _) = op {
+            for (_, info) in methods {
+                info.start = map[info.start];
+            }
+        }
+    }
+    new_code
+}
+
+/// The instructions execution can continue at other than by falling through
+/// from the instruction before: jump targets and method starts.
+fn leaders(code: &[OpCode]) -> Vec<bool> {
+    let mut leaders = vec![false; code.len() + 1];
+    leaders[0] = true;
+    for op in code {
+        if let Some(target) = jump_target(op) {
+            leaders[target] = true;
+        }
+        if let OpCode::Instance(_, methods, _) = op {
+            for (_, info) in methods {
+                leaders[info.start] = true;
+            }
+        }
+    }
+    leaders
+}
+
+/// Tries the patterns at `i`, longest first. Returns how many instructions
+/// were used and what replaces them.
+fn peephole(code: &[OpCode], leaders: &[bool], i: usize) -> (usize, Option<OpCode>) {
+    // The instructions at `i` if none of them but the first is a jump target.
+    let window = |n: usize| -> Option<&[OpCode]> {
+        let ops = code.get(i..i + n)?;
+        (!leaders[i + 1..i + n].iter().any(|l| *l)).then_some(ops)
+    };
+    use OpCode::*;
+
+    if let Some([GetLocal(a), PushInt(k), Plus, SetLocal(b)]) = window(4) {
+        if a == b {
+            return (4, Some(AddLocal(*a, *k)));
+        }
+    }
+    if let Some([PushInt(a), PushInt(b), op]) = window(3) {
+        let (a, b) = (*a, *b);
+        let folded = match op {
+            Plus => a.checked_add(b).map(PushInt),
+            Minus => a.checked_sub(b).map(PushInt),
+            Multiply => a.checked_mul(b).map(PushInt),
+            Less => Some(PushBool(a < b)),
+            LessEqual => Some(PushBool(a <= b)),
+            Greater => Some(PushBool(a > b)),
+            GreaterEqual => Some(PushBool(a >= b)),
+            Equals => Some(PushBool(a == b)),
+            NotEquals => Some(PushBool(a != b)),
+            _ => None,
+        };
+        if folded.is_some() {
+            return (3, folded);
+        }
+    }
+    if let Some([PushBool(a), PushBool(b), op]) = window(3) {
+        let (a, b) = (*a, *b);
+        let folded = match op {
+            Equals => Some(PushBool(a == b)),
+            NotEquals => Some(PushBool(a != b)),
+            And => Some(PushBool(a && b)),
+            Or => Some(PushBool(a || b)),
+            _ => None,
+        };
+        if folded.is_some() {
+            return (3, folded);
+        }
+    }
+    match window(2) {
+        Some([PushBool(true), JumpIfFalse(_)]) => return (2, None),
+        Some([PushBool(false), JumpIfFalse(p)]) => return (2, Some(Jump(*p))),
+        Some([PushBool(b), Not]) => return (2, Some(PushBool(!b))),
+        Some([PushInt(v), Neg]) if *v != i32::MIN => return (2, Some(PushInt(-v))),
+        Some([PushFloat(v), Neg]) => return (2, Some(PushFloat(-v))),
+        Some([Less, JumpIfFalse(p)]) => return (2, Some(JumpIfNotLess(*p))),
+        Some([Equals, JumpIfFalse(p)]) => return (2, Some(JumpIfNotEqual(*p))),
+        Some([push, Pop]) if is_pure_push(push) => return (2, None),
+        _ => {}
+    }
+    match &code[i] {
+        Jump(p) if *p == i + 1 => (1, None),
+        op => (1, Some(op.clone())),
+    }
+}
+
+/// Pushes one value and does nothing else.
+fn is_pure_push(op: &OpCode) -> bool {
+    matches!(
+        op,
+        OpCode::PushInt(_)
+            | OpCode::PushBool(_)
+            | OpCode::PushFloat(_)
+            | OpCode::PushNil
+            | OpCode::PushString(_)
+            | OpCode::GetLocal(_)
+            | OpCode::GetGlobal(_)
+            | OpCode::GetClass(_)
+            | OpCode::PushSelf
+    )
+}
+
+fn jump_target(op: &OpCode) -> Option<usize> {
+    match op {
+        OpCode::Jump(p)
+        | OpCode::JumpIfFalse(p)
+        | OpCode::JumpIfNotLess(p)
+        | OpCode::JumpIfNotEqual(p) => Some(*p),
+        _ => None,
+    }
+}
+
+fn set_jump_target(op: &mut OpCode, target: usize) {
+    match op {
+        OpCode::Jump(p)
+        | OpCode::JumpIfFalse(p)
+        | OpCode::JumpIfNotLess(p)
+        | OpCode::JumpIfNotEqual(p) => *p = target,
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    /// Parses instructions separated by spaces, written like in a file.
+    fn code(text: &str) -> Vec<OpCode> {
+        text.split_whitespace()
+            .map(|op| OpCode::from_str(op).unwrap())
+            .collect()
+    }
+
+    fn show(code: &[OpCode]) -> Vec<String> {
+        code.iter().map(|op| format!("{:?}", op)).collect()
+    }
+
+    #[test]
+    fn sequences_are_rewritten() {
+        let res = optimize(code("GetLocal|0 PushInt|1 Plus SetLocal|0 Return"));
+        assert_eq!(show(&res), ["AddLocal(0, 1)", "Return"]);
+
+        let res = optimize(code(
+            "PushInt|2 PushInt|3 Multiply PushBool|true Not Return",
+        ));
+        assert_eq!(show(&res), ["PushInt(6)", "PushBool(false)", "Return"]);
+
+        let res = optimize(code(
+            "GetLocal|0 GetLocal|1 Less JumpIfFalse|0 PushNil Pop Return",
+        ));
+        assert_eq!(
+            show(&res),
+            ["GetLocal(0)", "GetLocal(1)", "JumpIfNotLess(0)", "Return"]
+        );
+    }
+
+    #[test]
+    fn sequences_with_a_jump_into_them_are_kept() {
+        let text = "GetLocal|1 JumpIfFalse|4 GetLocal|0 PushInt|1 Plus SetLocal|0 Return";
+        let res = optimize(code(text));
+        assert_eq!(show(&res), show(&code(text)));
+    }
+
+    #[test]
+    fn jump_targets_and_method_starts_are_renumbered() {
+        let text = "Instance||m|6|0|0| JumpIfFalse|6 PushInt|2 PushInt|3 Plus Pop PushNil Return";
+        let res = optimize(code(text));
+        assert_eq!(
+            show(&res),
+            [
+                r#"Instance([], [("m", MethodInfo { start: 2, arity: 0, required: 0 })], [])"#,
+                "JumpIfFalse(2)",
+                "PushNil",
+                "Return"
+            ]
+        );
+    }
+
+    #[test]
+    fn jumps_are_threaded() {
+        let mut jumps = code("JumpIfFalse|2 Return Jump|4 Return Jump|5 Return");
+        assert!(thread_jumps(&mut jumps));
+        assert_eq!(show(&jumps)[0], "JumpIfFalse(5)");
+        assert!(!thread_jumps(&mut jumps));
+    }
+
+    #[test]
+    fn loops_of_jumps_are_left_alone() {
+        let mut loop_code = code("Jump|1 Jump|2 Jump|0");
+        assert!(!thread_jumps(&mut loop_code));
+        assert_eq!(show(&loop_code), ["Jump(1)", "Jump(2)", "Jump(0)"]);
+
+        let res = optimize(code("Jump|1 Jump|0"));
+        assert_eq!(show(&res), ["Jump(0)"]);
+    }
+}
@@ -0,0 +1,136 @@
+use std::{
+    any::Any,
+    fs,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+};
+
+use crate::{cache_flags, compile, read_bytecode, run_compiler, vm::Vm};
+
+/// Runs the tests in every `*_test.lum` file under `paths`. A test is a
+/// `test_*` method on a class whose name ends with `Test`, and each one is
+/// compiled and run in its own VM. Returns the exit code for the process.
+pub fn run_tests(compiler_path: &str, paths: &[String]) -> i32 {
+    let mut files = vec![];
+    for path in paths {
+        find_test_files(Path::new(path), &mut files);
+    }
+    files.sort();
+
+    let out_path = std::env::temp_dir().join(format!("lum_test_{}.l", std::process::id()));
+    let out_path = out_path.to_string_lossy().to_string();
+
+    // Failing assertions panic, the message is reported with the test instead.
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+
+    let mut passed = 0;
+    let mut failures: Vec<(String, String)> = vec![];
+    for file in files {
+        let file = file.to_string_lossy().to_string();
+        let mut args = [&file, "--list-tests", "--out", &out_path]
+            .map(String::from)
+            .to_vec();
+        args.extend(cache_flags(compiler_path));
+        // Warnings are shown once for the file, not for every test in it.
+        let listed = run_compiler(compiler_path, args).map(|diagnostics| {
+            print!("{}", diagnostics);
+            fs::read_to_string(&out_path).unwrap_or_default()
+        });
+        let tests = match listed {
+            Ok(tests) => tests,
+            Err(e) => {
+                println!("test {} ... FAILED", file);
+                failures.push((file, e));
+                continue;
+            }
+        };
+        for test in tests.lines().filter(|t| !t.is_empty()) {
+            let name = format!("{}::{}", file, test);
+            let mut flags = vec!["--test".to_string(), test.to_string()];
+            flags.extend(cache_flags(compiler_path));
+            let res = compile(compiler_path, &file, &flags, &out_path).and_then(|_| run(&out_path));
+            match res {
+                Ok(()) => {
+                    println!("test {} ... ok", name);
+                    passed += 1;
+                }
+                Err(e) => {
+                    println!("test {} ... FAILED", name);
+                    failures.push((name, e));
+                }
+            }
+        }
+    }
+
+    panic::set_hook(hook);
+    let _ = fs::remove_file(&out_path);
+
+    if !failures.is_empty() {
+        println!("\nfailures:");
+        for (name, e) in &failures {
+            println!("\n---- {} ----\n{}", name, e);
+        }
+    }
+    let result = if failures.is_empty() { "ok" } else { "FAILED" };
+    println!(
+        "\ntest result: {}. {} passed; {} failed",
+        result,
+        passed,
+        failures.len()
+    );
+    if failures.is_empty() {
+        0
+    } else {
+        1
+    }
+}
+
+fn find_test_files(path: &Path, files: &mut Vec<PathBuf>) {
+    if path.is_file() {
+        files.push(path.to_path_buf());
+        return;
+    }
+    let Ok(entries) = fs::read_dir(path) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        if path.is_dir() && !name.starts_with('.') && name != "target" {
+            find_test_files(&path, files);
+        } else if name.ends_with("_test.lum") {
+            files.push(path);
+        }
+    }
+}
+
+fn run(path: &str) -> Result<(), String> {
+    let (code, strings) = read_bytecode(path)?;
+    let mut vm = Vm::new(code, strings).with_exit_unwinding();
+    capture(|out| vm.run(out))
+}
+
+/// Runs `f` with its output captured. If it panics, the output so far is
+/// returned together with the panic message.
+fn capture(f: impl FnOnce(&mut Vec<u8>)) -> Result<(), String> {
+    let mut out = vec![];
+    match panic::catch_unwind(AssertUnwindSafe(|| f(&mut out))) {
+        Ok(()) => Ok(()),
+        Err(e) => Err(format!(
+            "{}{}",
+            String::from_utf8_lossy(&out),
+            panic_message(&e)
+        )),
+    }
+}
+
+pub(crate) fn panic_message(e: &Box<dyn Any + Send>) -> String {
+    if let Some(s) = e.downcast_ref::<String>() {
+        s.clone()
+    } else if let Some(s) = e.downcast_ref::<&str>() {
+        s.to_string()
+    } else {
+        "test panicked".to_string()
+    }
+}
@@ -1,6 +1,17 @@
-use std::{collections::HashMap, io::Write};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, Write},
+};
 
-use crate::opcode::OpCode;
+use crate::opcode::{MethodInfo, OpCode};
+
+mod fs;
+mod json;
+mod lists;
+mod math;
+mod process;
+mod strings;
 
 pub struct Vm {
     code: Vec<OpCode>,
@@ -8,17 +19,35 @@ pub struct Vm {
     lists: Vec<Vec<Value>>,
     instances: Vec<InstanceObj>,
     call_stack: Vec<CallFrame>,
+    /// The variables declared at the top level of a module. They are nil
+    /// until they are assigned.
+    globals: Vec<Value>,
+    /// The class objects, which hold the static fields and methods of a
+    /// class. A class without them has none.
+    classes: Vec<Value>,
+    files: Vec<Option<BufReader<File>>>,
+    fs_error: Option<String>,
+    args: Vec<String>,
+    /// `#exit` panics instead of ending the process.
+    exit_unwinds: bool,
 }
 
 #[derive(Debug)]
 struct InstanceObj {
     variables: HashMap<String, Value>,
-    methods: HashMap<String, usize>,
+    methods: HashMap<String, MethodInfo>,
 }
 
 struct CallFrame {
     return_pos: usize,
     stack_offset: usize,
+    /// Set for `init`, which returns the instance it was called on.
+    constructor: bool,
+    /// How many arguments the caller gave, the others get their defaults.
+    args: usize,
+    /// For `init`, the fields that were left out when the instance was
+    /// created, they get their defaults.
+    unset: Vec<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -40,13 +69,39 @@ impl Vm {
             lists: vec![],
             instances: vec![],
             call_stack: vec![],
+            globals: vec![],
+            classes: vec![],
+            files: vec![],
+            fs_error: None,
+            args: std::env::args().skip(1).collect(),
+            exit_unwinds: false,
         }
     }
+
+    /// Replaces the arguments seen by `#args`, which default to the
+    /// arguments of the `lum` process.
+    pub fn with_args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
+    }
+
+    /// Makes `#exit` panic with its status, so a test that exits only ends
+    /// itself and not the test run.
+    pub(crate) fn with_exit_unwinding(mut self) -> Self {
+        self.exit_unwinds = true;
+        self
+    }
+
     pub fn run(&mut self, out: &mut impl Write) {
-        println!("started VM!");
-        let mut ip = 0;
-        let mut stack_offset = 0;
         let mut stack: Vec<Value> = Vec::with_capacity(64);
+        self.execute(out, &mut stack, 0);
+    }
+
+    /// Runs until `ip` points past the end of the code. A call frame with
+    /// `return_pos` set to `code.len()` therefore runs just that method,
+    /// which is how natives call back into lum code.
+    fn execute(&mut self, out: &mut impl Write, stack: &mut Vec<Value>, mut ip: usize) {
+        let mut stack_offset = self.call_stack.last().map_or(0, |f| f.stack_offset);
 
         while ip < self.code.len() {
             // println!("stack: {:?}", stack);
@@ -74,7 +129,7 @@ impl Vm {
                     self.lists.push(list);
                     ip += 1;
                 }
-                OpCode::Instance(ref field_names, ref method_names, ref starts) => {
+                OpCode::Instance(ref field_names, ref methods, ref unset) => {
                     let mut instance = InstanceObj {
                         variables: HashMap::new(),
                         methods: HashMap::new(),
@@ -85,30 +140,55 @@ impl Vm {
                             .insert(name.clone(), stack.pop().unwrap());
                     }
 
-                    for i in 0..(starts.len()) {
-                        instance.methods.insert(method_names[i].clone(), starts[i]);
+                    for (name, info) in methods {
+                        instance.methods.insert(name.clone(), *info);
                     }
+                    let init = instance.methods.get("init").copied();
                     stack.push(Value::Instance(self.instances.len()));
                     self.instances.push(instance);
-                    ip += 1;
+                    // A class with an `init` method runs it before the new
+                    // instance is used.
+                    match init {
+                        Some(init) => {
+                            stack_offset = stack.len() - 1;
+                            for _ in 0..init.arity {
+                                stack.push(Value::Nil);
+                            }
+                            self.call_stack.push(CallFrame {
+                                return_pos: ip + 1,
+                                stack_offset,
+                                constructor: true,
+                                args: 0,
+                                unset: unset.clone(),
+                            });
+                            ip = init.start;
+                        }
+                        None => ip += 1,
+                    }
                 }
                 OpCode::Less => {
                     let v2 = stack.pop().unwrap();
                     let v1 = stack.pop().unwrap();
-                    let b = match (v1, v2) {
-                        (Value::Float(f1), Value::Float(f2)) => f1 < f2,
-                        (Value::Int(i1), Value::Int(i2)) => i1 < i2,
-                        (a, b) => panic!("cant compare {:?}, {:?}", a, b),
-                    };
-                    stack.push(Value::Bool(b));
+                    stack.push(Value::Bool(less(v1, v2)));
                     ip += 1;
                 }
+                OpCode::JumpIfNotLess(p) => {
+                    let v2 = stack.pop().unwrap();
+                    let v1 = stack.pop().unwrap();
+                    ip = if less(v1, v2) { ip + 1 } else { p };
+                }
                 OpCode::Plus => {
                     let v2 = stack.pop().unwrap();
                     let v1 = stack.pop().unwrap();
                     match (v1, v2) {
                         (Value::Float(f1), Value::Float(f2)) => stack.push(Value::Float(f1 + f2)),
                         (Value::Int(i1), Value::Int(i2)) => stack.push(Value::Int(i1 + i2)),
+                        (Value::Int(i1), Value::Float(f2)) => {
+                            stack.push(Value::Float(i1 as f32 + f2))
+                        }
+                        (Value::Float(f1), Value::Int(i2)) => {
+                            stack.push(Value::Float(f1 + i2 as f32))
+                        }
                         (Value::String(s1), Value::String(s2)) => {
                             let new_string = self.strings[s1].clone() + &self.strings[s2];
                             stack.push(Value::String(self.strings.len()));
@@ -130,10 +210,49 @@ impl Vm {
                     match (v1, v2) {
                         (Value::Float(f1), Value::Float(f2)) => stack.push(Value::Float(f1 - f2)),
                         (Value::Int(i1), Value::Int(i2)) => stack.push(Value::Int(i1 - i2)),
+                        (Value::Int(i1), Value::Float(f2)) => {
+                            stack.push(Value::Float(i1 as f32 - f2))
+                        }
+                        (Value::Float(f1), Value::Int(i2)) => {
+                            stack.push(Value::Float(f1 - i2 as f32))
+                        }
                         _ => panic!("cant subtract"),
                     }
                     ip += 1;
                 }
+                OpCode::Multiply => {
+                    let v2 = stack.pop().unwrap();
+                    let v1 = stack.pop().unwrap();
+                    match (v1, v2) {
+                        (Value::Float(f1), Value::Float(f2)) => stack.push(Value::Float(f1 * f2)),
+                        (Value::Int(i1), Value::Int(i2)) => stack.push(Value::Int(i1 * i2)),
+                        (Value::Int(i1), Value::Float(f2)) => {
+                            stack.push(Value::Float(i1 as f32 * f2))
+                        }
+                        (Value::Float(f1), Value::Int(i2)) => {
+                            stack.push(Value::Float(f1 * i2 as f32))
+                        }
+                        (p1, p2) => panic!("cant multiply {:?} {:?}", p1, p2),
+                    }
+                    ip += 1;
+                }
+                OpCode::Divide => {
+                    let v2 = stack.pop().unwrap();
+                    let v1 = stack.pop().unwrap();
+                    match (v1, v2) {
+                        (Value::Int(_), Value::Int(0)) => panic!("division by zero"),
+                        (Value::Float(f1), Value::Float(f2)) => stack.push(Value::Float(f1 / f2)),
+                        (Value::Int(i1), Value::Int(i2)) => stack.push(Value::Int(i1 / i2)),
+                        (Value::Int(i1), Value::Float(f2)) => {
+                            stack.push(Value::Float(i1 as f32 / f2))
+                        }
+                        (Value::Float(f1), Value::Int(i2)) => {
+                            stack.push(Value::Float(f1 / i2 as f32))
+                        }
+                        (p1, p2) => panic!("cant divide {:?} {:?}", p1, p2),
+                    }
+                    ip += 1;
+                }
                 OpCode::JumpIfFalse(p) => {
                     let b = stack.pop().unwrap();
                     let v = match b {
@@ -153,6 +272,37 @@ impl Vm {
                     stack[v + stack_offset] = stack.pop().unwrap();
                     ip += 1;
                 }
+                OpCode::GetGlobal(g) => {
+                    stack.push(self.globals.get(g).cloned().unwrap_or(Value::Nil));
+                    ip += 1;
+                }
+                OpCode::SetGlobal(g) => {
+                    if g >= self.globals.len() {
+                        self.globals.resize(g + 1, Value::Nil);
+                    }
+                    self.globals[g] = stack.pop().unwrap();
+                    ip += 1;
+                }
+                OpCode::GetClass(c) => {
+                    stack.push(self.classes.get(c).cloned().unwrap_or(Value::Nil));
+                    ip += 1;
+                }
+                OpCode::SetClass(c) => {
+                    if c >= self.classes.len() {
+                        self.classes.resize(c + 1, Value::Nil);
+                    }
+                    self.classes[c] = stack.pop().unwrap();
+                    ip += 1;
+                }
+                OpCode::AddLocal(v, k) => {
+                    let local = &mut stack[v + stack_offset];
+                    *local = match *local {
+                        Value::Int(i) => Value::Int(i + k),
+                        Value::Float(f) => Value::Float(f + k as f32),
+                        ref p => panic!("cant add {:?} {:?}", p, Value::Int(k)),
+                    };
+                    ip += 1;
+                }
                 OpCode::Jump(p) => {
                     ip = p;
                 }
@@ -176,7 +326,7 @@ impl Vm {
                             write!(out, " ").unwrap();
                         }
                     }
-                    write!(out, "\n").unwrap();
+                    writeln!(out).unwrap();
                     stack.push(Value::Nil);
                     ip += 1;
                 }
@@ -199,14 +349,14 @@ impl Vm {
                         // #read_file
                         2 => match stack.pop().unwrap() {
                             Value::String(s) => {
-                                let content = match std::fs::read_to_string(&self.strings[s]) {
-                                    Ok(s) => s,
-                                    Err(e) => {
-                                        panic!("Error reading file: {} - {}", self.strings[s], e)
+                                let content = std::fs::read_to_string(&self.strings[s]);
+                                match self.fs_result(content) {
+                                    Some(content) => {
+                                        let s = self.new_string(content);
+                                        stack.push(s)
                                     }
-                                };
-                                stack.push(Value::String(self.strings.len()));
-                                self.strings.push(content);
+                                    None => stack.push(Value::Nil),
+                                }
                             }
                             _ => panic!("expected a string"),
                         },
@@ -251,28 +401,31 @@ impl Vm {
                         }
                         7 => match (stack.pop(), stack.pop()) {
                             (Some(Value::List(l)), Some(Value::String(path))) => {
-                                std::fs::write(
+                                let res = std::fs::write(
                                     &self.strings[path],
                                     self.lists[l]
                                         .iter()
                                         .map(|x| self.get_value_as_str(x))
                                         .collect::<Vec<String>>()
                                         .join("\n"),
-                                )
-                                .unwrap();
-                                stack.push(Value::Nil)
+                                );
+                                stack.push(self.fs_ok(res))
                             }
                             (Some(Value::String(s)), Some(Value::String(path))) => {
-                                std::fs::write(&self.strings[path], &self.strings[s]).unwrap();
-                                stack.push(Value::Nil)
+                                let res = std::fs::write(&self.strings[path], &self.strings[s]);
+                                stack.push(self.fs_ok(res))
                             }
                             _ => panic!("invalid args"),
                         },
                         8 => {
-                            let args: Vec<String> = std::env::args().collect();
                             if let Some(Value::Int(x)) = stack.pop() {
-                                stack.push(Value::String(self.strings.len()));
-                                self.strings.push(args[(x + 1) as usize].clone());
+                                match self.args.get(x as usize) {
+                                    Some(arg) if x >= 0 => {
+                                        let s = self.new_string(arg.clone());
+                                        stack.push(s)
+                                    }
+                                    _ => stack.push(Value::Nil),
+                                }
                             } else {
                                 panic!("invalid args")
                             }
@@ -294,6 +447,87 @@ impl Vm {
                             }
                             _ => panic!("invalid args"),
                         },
+                        // #char
+                        10 => match stack.pop() {
+                            Some(Value::Int(code)) => match char::from_u32(code as u32) {
+                                Some(c) => {
+                                    let s = self.new_string(c.to_string());
+                                    stack.push(s)
+                                }
+                                None => stack.push(Value::Nil),
+                            },
+                            _ => panic!("invalid args"),
+                        },
+                        // #int
+                        11 => match stack.pop() {
+                            Some(Value::Int(i)) => stack.push(Value::Int(i)),
+                            Some(Value::Float(f)) => stack.push(Value::Int(f as i32)),
+                            _ => panic!("invalid args"),
+                        },
+                        // #float
+                        12 => match stack.pop() {
+                            Some(Value::Int(i)) => stack.push(Value::Float(i as f32)),
+                            Some(Value::Float(f)) => stack.push(Value::Float(f)),
+                            _ => panic!("invalid args"),
+                        },
+                        // #args_count, #args_list, #env, #exit, #read_line, #read_stdin, #exec
+                        13..=19 => self.process_native(out, n, stack),
+                        // #json_parse, #json_stringify
+                        20 | 21 => self.json_native(n, stack),
+                        // #fields
+                        22 => match stack.pop() {
+                            Some(Value::Instance(i)) => {
+                                let mut names: Vec<String> =
+                                    self.instances[i].variables.keys().cloned().collect();
+                                names.sort();
+                                let list = names.into_iter().map(|n| self.new_string(n)).collect();
+                                let list = self.new_list(list);
+                                stack.push(list)
+                            }
+                            _ => panic!("invalid args"),
+                        },
+                        // #assert, the compiler passes the location of the call
+                        23 => match (stack.pop(), stack.pop(), stack.pop()) {
+                            (Some(location), Some(msg), Some(cond)) => {
+                                if !matches!(cond, Value::Bool(true)) {
+                                    panic!(
+                                        "assertion failed: {} at {}",
+                                        self.get_value_as_str(&msg),
+                                        self.get_value_as_str(&location)
+                                    )
+                                }
+                                stack.push(Value::Nil)
+                            }
+                            _ => panic!("invalid args"),
+                        },
+                        // #assert_eq
+                        24 => match (stack.pop(), stack.pop(), stack.pop()) {
+                            (Some(location), Some(right), Some(left)) => {
+                                if !self.values_equal(&left, &right) {
+                                    panic!(
+                                        "assertion failed: left == right at {}\n  left: {}\n right: {}",
+                                        self.get_value_as_str(&location),
+                                        self.get_value_as_str(&left),
+                                        self.get_value_as_str(&right)
+                                    )
+                                }
+                                stack.push(Value::Nil)
+                            }
+                            _ => panic!("invalid args"),
+                        },
+                        // #hash
+                        25 => match stack.pop() {
+                            Some(Value::String(s)) => {
+                                let hash = hash(self.strings[s].as_bytes());
+                                let hash = self.new_string(hash);
+                                stack.push(hash)
+                            }
+                            _ => panic!("#hash expects a string"),
+                        },
+                        // #math.*
+                        100..=199 => self.math_native(n, stack),
+                        // #fs.*
+                        200..=299 => self.fs_native(n, stack),
                         _ => panic!("native function {} not found", n),
                     }
                     ip += 1;
@@ -332,7 +566,7 @@ impl Vm {
                             //.variables[f]
                             .variables
                             .get(f)
-                            .expect(&format!("could not find variable {}", f))
+                            .unwrap_or_else(|| panic!("could not find variable {}", f))
                             .clone(),
                         p => panic!("get must be on instance {:?}", p),
                     };
@@ -359,35 +593,64 @@ impl Vm {
                     stack.push(stack[stack_offset].clone());
                     ip += 1;
                 }
+                OpCode::MissingArg(n) => {
+                    let given = self.call_stack.last().map_or(0, |f| f.args);
+                    stack.push(Value::Bool(n >= given));
+                    ip += 1;
+                }
+                OpCode::MissingField(ref name) => {
+                    let frame = self.call_stack.last();
+                    let unset = frame.is_some_and(|frame| frame.unset.contains(name));
+                    stack.push(Value::Bool(unset));
+                    ip += 1;
+                }
                 OpCode::Call(ref name, arity) => {
-                    stack_offset = stack.len() - arity;
-                    self.call_stack.push(CallFrame {
-                        return_pos: ip + 1,
-                        // arity,
-                        stack_offset,
-                    });
-
-                    match stack[stack_offset] {
+                    let receiver_pos = stack.len() - arity;
+                    match stack[receiver_pos].clone() {
                         Value::Instance(i) => {
-                            let iobc = &self.instances[i];
-                            // println!("name: {name}");
-                            // println!("iovbsc {:?}", iobc);
-                            ip = *iobc.methods.get(name).expect(&format!(
-                                "cound not find name on instance - name: {}",
-                                name
-                            ));
-                            // ip = iobc.methods[name];
+                            let method = self.find_method(i, name, arity - 1);
+                            // Missing arguments are nil, the method replaces
+                            // them with their default values.
+                            for _ in arity - 1..method.arity {
+                                stack.push(Value::Nil);
+                            }
+                            stack_offset = receiver_pos;
+                            self.call_stack.push(CallFrame {
+                                return_pos: ip + 1,
+                                // arity,
+                                stack_offset,
+                                constructor: false,
+                                args: arity - 1,
+                                unset: vec![],
+                            });
+                            ip = method.start;
+                        }
+                        // Methods on built-in types are implemented in rust
+                        // and run without a call frame.
+                        receiver => {
+                            let name = name.clone();
+                            let args = stack.split_off(receiver_pos + 1);
+                            stack.pop();
+                            let value = match receiver {
+                                Value::String(s) => self.string_method(s, &name, &args),
+                                Value::List(l) => self.list_method(out, stack, l, &name, &args),
+                                p => panic!("cant call method {} on {:?}", name, p),
+                            };
+                            stack.push(value);
+                            ip += 1;
                         }
-                        _ => panic!("not instance"),
                     }
                 }
                 OpCode::Return => {
-                    let value = stack.pop().unwrap();
+                    let mut value = stack.pop().unwrap();
 
                     let call_frame = self
                         .call_stack
                         .pop()
                         .expect("call stack should not be empty");
+                    if call_frame.constructor {
+                        value = stack[call_frame.stack_offset].clone();
+                    }
                     stack_offset = if let Some(so) = self.call_stack.last() {
                         so.stack_offset
                     } else {
@@ -403,6 +666,14 @@ impl Vm {
                     _ = stack.pop().unwrap();
                     ip += 1
                 }
+                OpCode::Neg => {
+                    match stack.pop() {
+                        Some(Value::Int(i)) => stack.push(Value::Int(-i)),
+                        Some(Value::Float(f)) => stack.push(Value::Float(-f)),
+                        _ => panic!("not a number"),
+                    }
+                    ip += 1
+                }
                 OpCode::Not => {
                     match stack.pop() {
                         Some(Value::Bool(b)) => stack.push(Value::Bool(!b)),
@@ -413,24 +684,15 @@ impl Vm {
                 OpCode::Equals => {
                     let v2 = stack.pop().unwrap();
                     let v1 = stack.pop().unwrap();
-                    let v = match (v1, v2) {
-                        (Value::Bool(v1), Value::Bool(v2)) => v1 == v2,
-                        (Value::Float(v1), Value::Float(v2)) => v1 == v2,
-                        (Value::Int(v1), Value::Int(v2)) => v1 == v2,
-                        (Value::String(v1), Value::String(v2)) => {
-                            self.strings[v1] == self.strings[v2]
-                        }
-                        (Value::List(v1), Value::List(v2)) => v1 == v2,
-                        (Value::Instance(v1), Value::Instance(v2)) => v1 == v2,
-                        (Value::Nil, Value::Nil) => true,
-                        (Value::Nil, _) => false,
-                        (_, Value::Nil) => false,
-                        (p1, p2) => panic!("cant compare types '{:?}', '{:?}'", p1, p2),
-                    };
-
+                    let v = self.equals(v1, v2);
                     stack.push(Value::Bool(v));
                     ip += 1
                 }
+                OpCode::JumpIfNotEqual(p) => {
+                    let v2 = stack.pop().unwrap();
+                    let v1 = stack.pop().unwrap();
+                    ip = if self.equals(v1, v2) { ip + 1 } else { p };
+                }
                 OpCode::NotEquals => {
                     let v2 = stack.pop().unwrap();
                     let v1 = stack.pop().unwrap();
@@ -438,6 +700,8 @@ impl Vm {
                         (Value::Bool(v1), Value::Bool(v2)) => v1 != v2,
                         (Value::Float(v1), Value::Float(v2)) => v1 != v2,
                         (Value::Int(v1), Value::Int(v2)) => v1 != v2,
+                        (Value::Int(v1), Value::Float(v2)) => v1 as f32 != v2,
+                        (Value::Float(v1), Value::Int(v2)) => v1 != v2 as f32,
                         (Value::String(v1), Value::String(v2)) => {
                             self.strings[v1] != self.strings[v2]
                         }
@@ -478,6 +742,8 @@ impl Vm {
                     let b = match (v1, v2) {
                         (Value::Float(f1), Value::Float(f2)) => f1 <= f2,
                         (Value::Int(i1), Value::Int(i2)) => i1 <= i2,
+                        (Value::Int(i1), Value::Float(f2)) => (i1 as f32) <= f2,
+                        (Value::Float(f1), Value::Int(i2)) => f1 <= i2 as f32,
                         (a, b) => panic!("cant compare {:?}, {:?}", a, b),
                     };
                     stack.push(Value::Bool(b));
@@ -489,6 +755,8 @@ impl Vm {
                     let b = match (v1, v2) {
                         (Value::Float(f1), Value::Float(f2)) => f1 > f2,
                         (Value::Int(i1), Value::Int(i2)) => i1 > i2,
+                        (Value::Int(i1), Value::Float(f2)) => (i1 as f32) > f2,
+                        (Value::Float(f1), Value::Int(i2)) => f1 > i2 as f32,
                         (a, b) => panic!("cant compare {:?}, {:?}", a, b),
                     };
                     stack.push(Value::Bool(b));
@@ -500,6 +768,8 @@ impl Vm {
                     let b = match (v1, v2) {
                         (Value::Float(f1), Value::Float(f2)) => f1 >= f2,
                         (Value::Int(i1), Value::Int(i2)) => i1 >= i2,
+                        (Value::Int(i1), Value::Float(f2)) => (i1 as f32) >= f2,
+                        (Value::Float(f1), Value::Int(i2)) => f1 >= i2 as f32,
                         (a, b) => panic!("cant compare {:?}, {:?}", a, b),
                     };
                     stack.push(Value::Bool(b));
@@ -512,13 +782,31 @@ impl Vm {
                         (Value::Int(i), Value::List(l)) => {
                             stack.push(self.lists[l][i as usize].clone())
                         }
-                        // todo: Not sure if this should exist
+                        // Strings are indexed by byte. The character starting at
+                        // that byte is returned, or "" inside a multi-byte character.
                         (Value::Int(i), Value::String(s)) => {
-                            let s_idx = self.strings.len();
-                            let new_s = &self.strings[s][(i as usize)..((i + 1) as usize)];
-                            self.strings.push(new_s.to_string());
-                            stack.push(Value::String(s_idx));
+                            let string = &self.strings[s];
+                            if i < 0 || i as usize >= string.len() {
+                                panic!(
+                                    "index {} out of range for string of length {}",
+                                    i,
+                                    string.len()
+                                )
+                            }
+                            let new_s = match string.get(i as usize..) {
+                                Some(rest) => rest.chars().next().unwrap().to_string(),
+                                None => String::new(),
+                            };
+                            let s = self.new_string(new_s);
+                            stack.push(s);
                         }
+                        (Value::String(key), Value::Instance(i)) => stack.push(
+                            self.instances[i]
+                                .variables
+                                .get(&self.strings[key])
+                                .cloned()
+                                .unwrap_or(Value::Nil),
+                        ),
                         _ => panic!("must be int and list"),
                     }
                     ip += 1;
@@ -529,15 +817,113 @@ impl Vm {
                     let list = stack.pop().unwrap();
                     match (indexer, list) {
                         (Value::Int(i), Value::List(l)) => self.lists[l][i as usize] = new_value,
+                        (Value::String(key), Value::Instance(i)) => {
+                            let key = self.strings[key].clone();
+                            self.instances[i].variables.insert(key, new_value);
+                        }
                         _ => panic!("must be int and list"),
                     }
                     ip += 1;
                 }
-                _ => todo!("instruction not implemented: {:?}", self.code[ip]),
             }
         }
     }
 
+    fn equals(&self, v1: Value, v2: Value) -> bool {
+        match (v1, v2) {
+            (Value::Bool(v1), Value::Bool(v2)) => v1 == v2,
+            (Value::Float(v1), Value::Float(v2)) => v1 == v2,
+            (Value::Int(v1), Value::Int(v2)) => v1 == v2,
+            (Value::Int(v1), Value::Float(v2)) => v1 as f32 == v2,
+            (Value::Float(v1), Value::Int(v2)) => v1 == v2 as f32,
+            (Value::String(v1), Value::String(v2)) => self.strings[v1] == self.strings[v2],
+            (Value::List(v1), Value::List(v2)) => v1 == v2,
+            (Value::Instance(v1), Value::Instance(v2)) => v1 == v2,
+            (Value::Nil, Value::Nil) => true,
+            (Value::Nil, _) => false,
+            (_, Value::Nil) => false,
+            (p1, p2) => panic!("cant compare types '{:?}', '{:?}'", p1, p2),
+        }
+    }
+
+    /// Calls a method on an instance from rust and returns its result.
+    fn call_method(
+        &mut self,
+        out: &mut impl Write,
+        stack: &mut Vec<Value>,
+        receiver: Value,
+        name: &str,
+        args: &[Value],
+    ) -> Value {
+        let method = match receiver {
+            Value::Instance(i) => self.find_method(i, name, args.len()),
+            ref p => panic!("cant call method {} on {:?}", name, p),
+        };
+        let stack_offset = stack.len();
+        stack.push(receiver);
+        stack.extend_from_slice(args);
+        for _ in args.len()..method.arity {
+            stack.push(Value::Nil);
+        }
+        self.call_stack.push(CallFrame {
+            return_pos: self.code.len(),
+            stack_offset,
+            constructor: false,
+            args: args.len(),
+            unset: vec![],
+        });
+        self.execute(out, stack, method.start);
+        stack.pop().unwrap()
+    }
+
+    /// Looks up a method and checks that it can be called with `given`
+    /// arguments.
+    fn find_method(&self, instance: usize, name: &str, given: usize) -> MethodInfo {
+        let method = *self.instances[instance]
+            .methods
+            .get(name)
+            .unwrap_or_else(|| panic!("cound not find name on instance - name: {}", name));
+        if given < method.required || given > method.arity {
+            let arguments = match method.arity {
+                1 => "1 argument".to_string(),
+                n => format!("{} arguments", n),
+            };
+            let expected = if method.required == method.arity {
+                arguments
+            } else {
+                format!("{} to {}", method.required, arguments)
+            };
+            panic!(
+                "method '{}' takes {} but {} were given",
+                name, expected, given
+            );
+        }
+        method
+    }
+
+    /// Creates an instance without methods, used by natives that return
+    /// several values.
+    fn new_record(&mut self, fields: Vec<(&str, Value)>) -> Value {
+        self.instances.push(InstanceObj {
+            variables: fields
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+            methods: HashMap::new(),
+        });
+        Value::Instance(self.instances.len() - 1)
+    }
+
+    fn new_list(&mut self, list: Vec<Value>) -> Value {
+        self.lists.push(list);
+        Value::List(self.lists.len() - 1)
+    }
+
+    fn new_string(&mut self, s: String) -> Value {
+        self.strings.push(s);
+        Value::String(self.strings.len() - 1)
+    }
+
     fn get_value_as_str(&self, val: &Value) -> String {
         match val {
             Value::Bool(b) => format!("{}", b),
@@ -561,7 +947,28 @@ impl Vm {
                     .collect::<Vec<String>>()
                     .join(", ")
             ),
-            Value::Nil => format!("nil"),
+            Value::Nil => "nil".to_string(),
         }
     }
 }
+
+/// The 64 bit FNV-1a hash of `data` as 16 hex digits. It does not change
+/// between runs or versions, so it can be used in file names.
+pub(crate) fn hash(data: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in data {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+fn less(v1: Value, v2: Value) -> bool {
+    match (v1, v2) {
+        (Value::Float(f1), Value::Float(f2)) => f1 < f2,
+        (Value::Int(i1), Value::Int(i2)) => i1 < i2,
+        (Value::Int(i1), Value::Float(f2)) => (i1 as f32) < f2,
+        (Value::Float(f1), Value::Int(i2)) => f1 < i2 as f32,
+        (a, b) => panic!("cant compare {:?}, {:?}", a, b),
+    }
+}
@@ -0,0 +1,189 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    time::UNIX_EPOCH,
+};
+
+use super::{Value, Vm};
+
+impl Vm {
+    /// Natives in the `fs` module, called as `#fs.exists(path)`. Failing
+    /// operations return nil and the reason can be read with `#fs.error()`.
+    pub(super) fn fs_native(&mut self, n: usize, stack: &mut Vec<Value>) {
+        let value = match n {
+            // #fs.exists
+            200 => Value::Bool(fs::metadata(self.pop_path(stack)).is_ok()),
+            // #fs.is_dir
+            201 => Value::Bool(fs::metadata(self.pop_path(stack)).is_ok_and(|m| m.is_dir())),
+            // #fs.list_dir
+            202 => {
+                let path = self.pop_path(stack);
+                let entries = fs::read_dir(&path).and_then(|dir| {
+                    dir.map(|entry| Ok(entry?.file_name().to_string_lossy().to_string()))
+                        .collect::<io::Result<Vec<String>>>()
+                });
+                match self.fs_result(entries) {
+                    Some(mut names) => {
+                        names.sort();
+                        let list = names.into_iter().map(|n| self.new_string(n)).collect();
+                        self.new_list(list)
+                    }
+                    None => Value::Nil,
+                }
+            }
+            // #fs.mkdir_all
+            203 => {
+                let res = fs::create_dir_all(self.pop_path(stack));
+                self.fs_ok(res)
+            }
+            // #fs.remove
+            204 => {
+                let path = self.pop_path(stack);
+                let res = match fs::metadata(&path) {
+                    Ok(m) if m.is_dir() => fs::remove_dir_all(&path),
+                    _ => fs::remove_file(&path),
+                };
+                self.fs_ok(res)
+            }
+            // #fs.rename
+            205 => {
+                let to = self.pop_path(stack);
+                let from = self.pop_path(stack);
+                let res = fs::rename(from, to);
+                self.fs_ok(res)
+            }
+            // #fs.append_file
+            206 => {
+                let content = match stack.pop() {
+                    Some(v) => self.get_value_as_str(&v),
+                    None => panic!("invalid args"),
+                };
+                let path = self.pop_path(stack);
+                let res = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .and_then(|mut f| f.write_all(content.as_bytes()));
+                self.fs_ok(res)
+            }
+            // #fs.metadata
+            207 => {
+                let path = self.pop_path(stack);
+                match self.fs_result(fs::metadata(path)) {
+                    Some(m) => {
+                        let modified = m
+                            .modified()
+                            .ok()
+                            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                            .map_or(Value::Nil, |d| number(d.as_secs()));
+                        self.new_record(vec![
+                            ("size", number(m.len())),
+                            ("is_dir", Value::Bool(m.is_dir())),
+                            ("is_file", Value::Bool(m.is_file())),
+                            ("modified", modified),
+                        ])
+                    }
+                    None => Value::Nil,
+                }
+            }
+            // #fs.open
+            208 => {
+                let path = self.pop_path(stack);
+                match self.fs_result(File::open(path)) {
+                    Some(f) => {
+                        self.files.push(Some(BufReader::new(f)));
+                        Value::Int(self.files.len() as i32 - 1)
+                    }
+                    None => Value::Nil,
+                }
+            }
+            // #fs.read_line
+            209 => {
+                let handle = self.pop_handle(stack);
+                let mut line = String::new();
+                let res = match self.files.get_mut(handle) {
+                    Some(Some(f)) => f.read_line(&mut line),
+                    _ => Err(io::Error::other(format!(
+                        "file handle {} is not open",
+                        handle
+                    ))),
+                };
+                match self.fs_result(res) {
+                    Some(0) | None => Value::Nil,
+                    Some(_) => {
+                        let len = line.trim_end_matches(['\n', '\r']).len();
+                        line.truncate(len);
+                        self.new_string(line)
+                    }
+                }
+            }
+            // #fs.close
+            210 => {
+                let handle = self.pop_handle(stack);
+                if let Some(f) = self.files.get_mut(handle) {
+                    *f = None;
+                }
+                Value::Nil
+            }
+            // #fs.error
+            211 => match self.fs_error.clone() {
+                Some(e) => self.new_string(e),
+                None => Value::Nil,
+            },
+            // #fs.canonicalize
+            212 => {
+                let path = self.pop_path(stack);
+                match self.fs_result(fs::canonicalize(path)) {
+                    Some(path) => self.new_string(path.to_string_lossy().to_string()),
+                    None => Value::Nil,
+                }
+            }
+            _ => panic!("native function {} not found", n),
+        };
+        stack.push(value);
+    }
+
+    /// Records the error of a failed operation so it can be read with
+    /// `#fs.error()`.
+    pub(super) fn fs_result<T>(&mut self, res: io::Result<T>) -> Option<T> {
+        match res {
+            Ok(v) => {
+                self.fs_error = None;
+                Some(v)
+            }
+            Err(e) => {
+                self.fs_error = Some(e.to_string());
+                None
+            }
+        }
+    }
+
+    pub(super) fn fs_ok(&mut self, res: io::Result<()>) -> Value {
+        match self.fs_result(res) {
+            Some(()) => Value::Bool(true),
+            None => Value::Nil,
+        }
+    }
+
+    fn pop_path(&self, stack: &mut Vec<Value>) -> String {
+        match stack.pop() {
+            Some(Value::String(s)) => self.strings[s].clone(),
+            p => panic!("expected a path but got {:?}", p),
+        }
+    }
+
+    fn pop_handle(&self, stack: &mut Vec<Value>) -> usize {
+        match stack.pop() {
+            Some(Value::Int(h)) if h >= 0 => h as usize,
+            p => panic!("expected a file handle but got {:?}", p),
+        }
+    }
+}
+
+/// Sizes and times that don't fit in an int are a float.
+fn number(n: u64) -> Value {
+    match i32::try_from(n) {
+        Ok(i) => Value::Int(i),
+        Err(_) => Value::Float(n as f32),
+    }
+}
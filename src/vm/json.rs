@@ -0,0 +1,341 @@
+use super::{Value, Vm};
+
+impl Vm {
+    /// `#json_parse(text)` and `#json_stringify(value, indent)`. Objects are
+    /// represented as instances without methods.
+    pub(super) fn json_native(&mut self, n: usize, stack: &mut Vec<Value>) {
+        let value = match n {
+            // #json_parse
+            20 => match stack.pop() {
+                Some(Value::String(s)) => {
+                    let src = self.strings[s].clone();
+                    let mut parser = JsonParser {
+                        vm: self,
+                        src: src.as_bytes(),
+                        pos: 0,
+                    };
+                    parser.parse()
+                }
+                _ => panic!("invalid args"),
+            },
+            // #json_stringify
+            21 => {
+                let indent = match stack.pop() {
+                    Some(Value::Int(i)) if i >= 0 => i as usize,
+                    Some(Value::Nil) => 0,
+                    _ => panic!("invalid args"),
+                };
+                let value = stack.pop().unwrap();
+                let mut res = String::new();
+                self.stringify(&value, indent, 0, &mut vec![], &mut res);
+                self.new_string(res)
+            }
+            _ => panic!("native function {} not found", n),
+        };
+        stack.push(value);
+    }
+
+    fn stringify(
+        &self,
+        value: &Value,
+        indent: usize,
+        depth: usize,
+        visiting: &mut Vec<Value>,
+        res: &mut String,
+    ) {
+        match value {
+            Value::Nil => res.push_str("null"),
+            Value::Bool(b) => res.push_str(&b.to_string()),
+            Value::Int(i) => res.push_str(&i.to_string()),
+            Value::Float(f) if f.is_finite() => {
+                res.push_str(&f.to_string());
+                // Whole floats keep a fraction so they are parsed as floats.
+                if f.fract() == 0.0 {
+                    res.push_str(".0");
+                }
+            }
+            Value::Float(f) => panic!("json_stringify: cant serialize {}", f),
+            Value::String(s) => push_json_string(&self.strings[*s], res),
+            Value::List(l) => {
+                self.enter(value, visiting);
+                res.push('[');
+                for (i, item) in self.lists[*l].iter().enumerate() {
+                    if i > 0 {
+                        res.push(',');
+                    }
+                    newline(indent, depth + 1, res);
+                    self.stringify(item, indent, depth + 1, visiting, res);
+                }
+                if !self.lists[*l].is_empty() {
+                    newline(indent, depth, res);
+                }
+                res.push(']');
+                visiting.pop();
+            }
+            Value::Instance(o) => {
+                self.enter(value, visiting);
+                let mut fields: Vec<(&String, &Value)> =
+                    self.instances[*o].variables.iter().collect();
+                fields.sort_by(|a, b| a.0.cmp(b.0));
+                res.push('{');
+                for (i, (key, item)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        res.push(',');
+                    }
+                    newline(indent, depth + 1, res);
+                    push_json_string(key, res);
+                    res.push(':');
+                    if indent > 0 {
+                        res.push(' ');
+                    }
+                    self.stringify(item, indent, depth + 1, visiting, res);
+                }
+                if !fields.is_empty() {
+                    newline(indent, depth, res);
+                }
+                res.push('}');
+                visiting.pop();
+            }
+        }
+    }
+
+    fn enter(&self, value: &Value, visiting: &mut Vec<Value>) {
+        let seen = visiting.iter().any(|v| match (v, value) {
+            (Value::List(a), Value::List(b)) => a == b,
+            (Value::Instance(a), Value::Instance(b)) => a == b,
+            _ => false,
+        });
+        if seen {
+            panic!("json_stringify: value contains a cycle");
+        }
+        visiting.push(value.clone());
+    }
+}
+
+fn newline(indent: usize, depth: usize, res: &mut String) {
+    if indent > 0 {
+        res.push('\n');
+        res.push_str(&" ".repeat(indent * depth));
+    }
+}
+
+fn push_json_string(s: &str, res: &mut String) {
+    res.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            '\r' => res.push_str("\\r"),
+            '\t' => res.push_str("\\t"),
+            c if (c as u32) < 0x20 => res.push_str(&format!("\\u{:04x}", c as u32)),
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+}
+
+struct JsonParser<'a> {
+    vm: &'a mut Vm,
+    src: &'a [u8],
+    pos: usize,
+}
+
+impl JsonParser<'_> {
+    fn parse(&mut self) -> Value {
+        let value = self.value();
+        self.whitespace();
+        if self.pos < self.src.len() {
+            self.error("unexpected trailing characters");
+        }
+        value
+    }
+
+    fn error(&self, msg: &str) -> ! {
+        panic!("json_parse: {} at position {}", msg, self.pos)
+    }
+
+    fn whitespace(&mut self) {
+        while self.pos < self.src.len() && self.src[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.whitespace();
+        self.src.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, c: u8) {
+        if self.peek() != Some(c) {
+            self.error(&format!("expected '{}'", c as char));
+        }
+        self.pos += 1;
+    }
+
+    fn keyword(&mut self, word: &str, value: Value) -> Value {
+        if !self.src[self.pos..].starts_with(word.as_bytes()) {
+            self.error("unexpected token");
+        }
+        self.pos += word.len();
+        value
+    }
+
+    fn value(&mut self) -> Value {
+        match self.peek() {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.list(),
+            Some(b'"') => {
+                let s = self.string();
+                self.vm.new_string(s)
+            }
+            Some(b't') => self.keyword("true", Value::Bool(true)),
+            Some(b'f') => self.keyword("false", Value::Bool(false)),
+            Some(b'n') => self.keyword("null", Value::Nil),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => self.error("unexpected token"),
+            None => self.error("unexpected end of input"),
+        }
+    }
+
+    fn object(&mut self) -> Value {
+        self.expect(b'{');
+        let mut fields = vec![];
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+        } else {
+            loop {
+                if self.peek() != Some(b'"') {
+                    self.error("expected a string key");
+                }
+                let key = self.string();
+                self.expect(b':');
+                let value = self.value();
+                fields.push((key, value));
+                match self.peek() {
+                    Some(b',') => self.pos += 1,
+                    Some(b'}') => {
+                        self.pos += 1;
+                        break;
+                    }
+                    _ => self.error("expected ',' or '}'"),
+                }
+            }
+        }
+        let fields = fields
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.clone()))
+            .collect();
+        self.vm.new_record(fields)
+    }
+
+    fn list(&mut self) -> Value {
+        self.expect(b'[');
+        let mut items = vec![];
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+        } else {
+            loop {
+                items.push(self.value());
+                match self.peek() {
+                    Some(b',') => self.pos += 1,
+                    Some(b']') => {
+                        self.pos += 1;
+                        break;
+                    }
+                    _ => self.error("expected ',' or ']'"),
+                }
+            }
+        }
+        self.vm.new_list(items)
+    }
+
+    fn string(&mut self) -> String {
+        self.pos += 1;
+        let mut bytes = vec![];
+        loop {
+            match self.src.get(self.pos) {
+                None => self.error("unterminated string"),
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let c = match self.src.get(self.pos) {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => self.unicode_escape(),
+                        _ => self.error("invalid escape"),
+                    };
+                    let mut buf = [0; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                    self.pos += 1;
+                }
+                Some(c) if *c < 0x20 => self.error("control character in string"),
+                Some(c) => {
+                    bytes.push(*c);
+                    self.pos += 1;
+                }
+            }
+        }
+        self.pos += 1;
+        String::from_utf8(bytes).unwrap_or_else(|_| self.error("invalid utf-8"))
+    }
+
+    /// Reads the `XXXX` of a `\uXXXX` escape, combining surrogate pairs.
+    /// Leaves `pos` on the last hex digit.
+    fn unicode_escape(&mut self) -> char {
+        let high = self.hex4();
+        if !(0xD800..0xDC00).contains(&high) {
+            return char::from_u32(high).unwrap_or_else(|| self.error("invalid unicode escape"));
+        }
+        if !self.src[self.pos + 1..].starts_with(b"\\u") {
+            self.error("unpaired surrogate");
+        }
+        self.pos += 2;
+        let low = self.hex4();
+        if !(0xDC00..0xE000).contains(&low) {
+            self.error("unpaired surrogate");
+        }
+        char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00))
+            .unwrap_or_else(|| self.error("invalid unicode escape"))
+    }
+
+    fn hex4(&mut self) -> u32 {
+        let hex = self
+            .src
+            .get(self.pos + 1..self.pos + 5)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u32::from_str_radix(h, 16).ok())
+            .unwrap_or_else(|| self.error("invalid unicode escape"));
+        self.pos += 4;
+        hex
+    }
+
+    fn number(&mut self) -> Value {
+        let start = self.pos;
+        let mut is_float = false;
+        while let Some(c) = self.src.get(self.pos) {
+            match c {
+                b'0'..=b'9' | b'-' | b'+' => {}
+                b'.' | b'e' | b'E' => is_float = true,
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        let text = std::str::from_utf8(&self.src[start..self.pos]).unwrap();
+        if !is_float {
+            if let Ok(i) = text.parse() {
+                return Value::Int(i);
+            }
+        }
+        match text.parse() {
+            Ok(f) => Value::Float(f),
+            Err(_) => self.error("invalid number"),
+        }
+    }
+}
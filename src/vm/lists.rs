@@ -0,0 +1,183 @@
+use std::{cmp::Ordering, io::Write};
+
+use super::{Value, Vm};
+
+impl Vm {
+    /// Runs a built-in method on a list, e.g. `items.index_of(x)`.
+    pub(super) fn list_method(
+        &mut self,
+        out: &mut impl Write,
+        stack: &mut Vec<Value>,
+        l: usize,
+        name: &str,
+        args: &[Value],
+    ) -> Value {
+        match (name, args) {
+            ("insert", [Value::Int(i), value]) => {
+                let i = self.list_index(l, *i, self.lists[l].len() + 1);
+                self.lists[l].insert(i, value.clone());
+                Value::Nil
+            }
+            ("remove_at", [Value::Int(i)]) => {
+                let i = self.list_index(l, *i, self.lists[l].len());
+                self.lists[l].remove(i)
+            }
+            ("slice", [Value::Int(start), Value::Int(end)]) => {
+                let len = self.lists[l].len() as i32;
+                let end = (*end).clamp(0, len) as usize;
+                let start = (*start).clamp(0, end as i32) as usize;
+                let new_list = self.lists[l][start..end].to_vec();
+                self.new_list(new_list)
+            }
+            ("reverse", []) => {
+                self.lists[l].reverse();
+                Value::Nil
+            }
+            ("contains", [value]) => {
+                Value::Bool(self.lists[l].iter().any(|x| self.values_equal(x, value)))
+            }
+            ("get", [Value::Int(i)]) => match usize::try_from(*i) {
+                Ok(i) => self.lists[l].get(i).cloned().unwrap_or(Value::Nil),
+                Err(_) => Value::Nil,
+            },
+            ("index_of", [value]) => {
+                match self.lists[l]
+                    .iter()
+                    .position(|x| self.values_equal(x, value))
+                {
+                    Some(i) => Value::Int(i as i32),
+                    None => Value::Nil,
+                }
+            }
+            ("extend", [Value::List(other)]) => {
+                let other = self.lists[*other].clone();
+                self.lists[l].extend(other);
+                Value::Nil
+            }
+            ("sort", []) => {
+                let mut list = self.lists[l].clone();
+                list.sort_by(|a, b| self.compare_values(a, b));
+                self.lists[l] = list;
+                Value::Nil
+            }
+            // The comparator can look at the list, so it is left as it is
+            // until the sorted copy is done.
+            ("sort", [comparator]) => {
+                let list = self.lists[l].clone();
+                let mut compare =
+                    |a: &Value, b: &Value| self.compare_with(out, stack, comparator, a, b);
+                let list = merge_sort(list, &mut compare);
+                if list
+                    .windows(2)
+                    .any(|w| compare(&w[0], &w[1]) == Ordering::Greater)
+                {
+                    panic!("compare does not give a consistent order");
+                }
+                self.lists[l] = list;
+                Value::Nil
+            }
+            ("binary_search", [value]) => {
+                let res = self.lists[l].binary_search_by(|x| self.compare_values(x, value));
+                match res {
+                    Ok(i) => Value::Int(i as i32),
+                    Err(_) => Value::Nil,
+                }
+            }
+            ("binary_search", [value, comparator]) => {
+                let list = self.lists[l].clone();
+                let res =
+                    list.binary_search_by(|x| self.compare_with(out, stack, comparator, x, value));
+                match res {
+                    Ok(i) => Value::Int(i as i32),
+                    Err(_) => Value::Nil,
+                }
+            }
+            (name, args) => panic!(
+                "no list method named {} taking {} arguments",
+                name,
+                args.len()
+            ),
+        }
+    }
+
+    fn list_index(&self, l: usize, i: i32, len: usize) -> usize {
+        if i < 0 || i as usize >= len {
+            panic!(
+                "index {} out of range for list of length {}",
+                i,
+                self.lists[l].len()
+            )
+        }
+        i as usize
+    }
+
+    /// Calls `comparator.compare(a, b)`, which should return an int that is
+    /// negative, zero or positive.
+    fn compare_with(
+        &mut self,
+        out: &mut impl Write,
+        stack: &mut Vec<Value>,
+        comparator: &Value,
+        a: &Value,
+        b: &Value,
+    ) -> Ordering {
+        let args = [a.clone(), b.clone()];
+        match self.call_method(out, stack, comparator.clone(), "compare", &args) {
+            Value::Int(i) => i.cmp(&0),
+            p => panic!("compare must return an int but got {:?}", p),
+        }
+    }
+
+    fn compare_values(&self, a: &Value, b: &Value) -> Ordering {
+        match (a, b) {
+            (Value::Int(i1), Value::Int(i2)) => i1.cmp(i2),
+            (Value::Float(f1), Value::Float(f2)) => f1.total_cmp(f2),
+            (Value::Int(i), Value::Float(f)) => (*i as f32).total_cmp(f),
+            (Value::Float(f), Value::Int(i)) => f.total_cmp(&(*i as f32)),
+            (Value::Bool(b1), Value::Bool(b2)) => b1.cmp(b2),
+            (Value::String(s1), Value::String(s2)) => self.strings[*s1].cmp(&self.strings[*s2]),
+            (a, b) => panic!("cant compare {:?}, {:?}", a, b),
+        }
+    }
+
+    /// Like `OpCode::Equals`, but values of different types are never equal.
+    pub(super) fn values_equal(&self, a: &Value, b: &Value) -> bool {
+        match (a, b) {
+            (Value::Bool(v1), Value::Bool(v2)) => v1 == v2,
+            (Value::Float(v1), Value::Float(v2)) => v1 == v2,
+            (Value::Int(v1), Value::Int(v2)) => v1 == v2,
+            (Value::String(v1), Value::String(v2)) => self.strings[*v1] == self.strings[*v2],
+            (Value::List(v1), Value::List(v2)) => v1 == v2,
+            (Value::Instance(v1), Value::Instance(v2)) => v1 == v2,
+            (Value::Nil, Value::Nil) => true,
+            _ => false,
+        }
+    }
+}
+
+/// A stable merge sort. Unlike `sort_by` it does not panic when `compare`
+/// is not a total order, which a comparator written in lum may not be.
+fn merge_sort(
+    mut items: Vec<Value>,
+    compare: &mut impl FnMut(&Value, &Value) -> Ordering,
+) -> Vec<Value> {
+    if items.len() <= 1 {
+        return items;
+    }
+    let right = items.split_off(items.len() / 2);
+    let left = merge_sort(items, compare);
+    let right = merge_sort(right, compare);
+    let mut res = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        if compare(b, a) == Ordering::Less {
+            res.extend(right.next());
+        } else {
+            res.extend(left.next());
+        }
+    }
+    res.extend(left);
+    res.extend(right);
+    res
+}
@@ -0,0 +1,107 @@
+use super::{Value, Vm};
+
+impl Vm {
+    /// Natives in the `math` module, called as `#math.sqrt(x)`.
+    pub(super) fn math_native(&mut self, n: usize, stack: &mut Vec<Value>) {
+        let value = match n {
+            // #math.floor, #math.ceil and #math.round, results that are not
+            // an int are a float
+            100..=102 => match stack.pop() {
+                Some(Value::Int(i)) => Value::Int(i),
+                Some(Value::Float(f)) => {
+                    let f = match n {
+                        100 => f.floor(),
+                        101 => f.ceil(),
+                        _ => f.round(),
+                    };
+                    // `i32::MAX as f32` rounds up to 2^31, which does not fit.
+                    if f >= i32::MIN as f32 && f < i32::MAX as f32 {
+                        Value::Int(f as i32)
+                    } else {
+                        Value::Float(f)
+                    }
+                }
+                p => panic!("expected a number but got {:?}", p),
+            },
+            // #math.abs
+            103 => match stack.pop() {
+                Some(Value::Int(i)) => match i.checked_abs() {
+                    Some(abs) => Value::Int(abs),
+                    None => Value::Float((i as f32).abs()),
+                },
+                Some(Value::Float(f)) => Value::Float(f.abs()),
+                p => panic!("expected a number but got {:?}", p),
+            },
+            // #math.min and #math.max
+            104 | 105 => {
+                let v2 = stack.pop().unwrap();
+                let v1 = stack.pop().unwrap();
+                let first = match (&v1, &v2) {
+                    (Value::Int(i1), Value::Int(i2)) => i1 < i2,
+                    _ => number(&v1) < number(&v2),
+                };
+                if first == (n == 104) {
+                    v1
+                } else {
+                    v2
+                }
+            }
+            // #math.sqrt
+            106 => Value::Float(pop_number(stack).sqrt()),
+            // #math.pow, ints that don't fit are a float
+            107 => match (stack.pop(), stack.pop()) {
+                (Some(Value::Int(exp)), Some(Value::Int(_))) if exp < 0 => {
+                    panic!("negative exponent {} for an int, use a float base", exp)
+                }
+                (Some(Value::Int(exp)), Some(Value::Int(base))) => {
+                    match base.checked_pow(exp as u32) {
+                        Some(res) => Value::Int(res),
+                        None => Value::Float((base as f32).powf(exp as f32)),
+                    }
+                }
+                (Some(exp), Some(base)) => Value::Float(number(&base).powf(number(&exp))),
+                _ => panic!("invalid args"),
+            },
+            // #math.sin
+            108 => Value::Float(pop_number(stack).sin()),
+            // #math.cos
+            109 => Value::Float(pop_number(stack).cos()),
+            // #math.tan
+            110 => Value::Float(pop_number(stack).tan()),
+            // #math.asin
+            111 => Value::Float(pop_number(stack).asin()),
+            // #math.acos
+            112 => Value::Float(pop_number(stack).acos()),
+            // #math.atan
+            113 => Value::Float(pop_number(stack).atan()),
+            // #math.atan2
+            114 => {
+                let x = pop_number(stack);
+                let y = pop_number(stack);
+                Value::Float(y.atan2(x))
+            }
+            // #math.exp
+            115 => Value::Float(pop_number(stack).exp()),
+            // #math.log
+            116 => Value::Float(pop_number(stack).ln()),
+            // #math.pi
+            117 => Value::Float(std::f32::consts::PI),
+            // #math.e
+            118 => Value::Float(std::f32::consts::E),
+            _ => panic!("native function {} not found", n),
+        };
+        stack.push(value);
+    }
+}
+
+fn number(v: &Value) -> f32 {
+    match v {
+        Value::Int(i) => *i as f32,
+        Value::Float(f) => *f,
+        p => panic!("expected a number but got {:?}", p),
+    }
+}
+
+fn pop_number(stack: &mut Vec<Value>) -> f32 {
+    number(&stack.pop().unwrap())
+}
@@ -0,0 +1,112 @@
+use std::{
+    io::{self, Read, Write},
+    process::Command,
+};
+
+use super::{Value, Vm};
+
+impl Vm {
+    /// Natives for talking to the environment the program runs in.
+    pub(super) fn process_native(
+        &mut self,
+        out: &mut impl Write,
+        n: usize,
+        stack: &mut Vec<Value>,
+    ) {
+        let value = match n {
+            // #args_count
+            13 => Value::Int(self.args.len() as i32),
+            // #args_list
+            14 => {
+                let args = self
+                    .args
+                    .clone()
+                    .into_iter()
+                    .map(|arg| self.new_string(arg))
+                    .collect();
+                self.new_list(args)
+            }
+            // #env
+            15 => match stack.pop() {
+                Some(Value::String(name)) => match std::env::var(&self.strings[name]) {
+                    Ok(value) => self.new_string(value),
+                    Err(_) => Value::Nil,
+                },
+                _ => panic!("invalid args"),
+            },
+            // #exit
+            16 => match stack.pop() {
+                Some(Value::Int(code)) => {
+                    out.flush().unwrap();
+                    if self.exit_unwinds {
+                        panic!("exited with status {}", code)
+                    }
+                    std::process::exit(code)
+                }
+                _ => panic!("invalid args"),
+            },
+            // #read_line
+            17 => {
+                let mut line = String::new();
+                match io::stdin().read_line(&mut line) {
+                    Ok(0) | Err(_) => Value::Nil,
+                    Ok(_) => {
+                        let len = line.trim_end_matches(['\n', '\r']).len();
+                        line.truncate(len);
+                        self.new_string(line)
+                    }
+                }
+            }
+            // #read_stdin
+            18 => {
+                let mut input = String::new();
+                match io::stdin().read_to_string(&mut input) {
+                    Ok(_) => self.new_string(input),
+                    Err(_) => Value::Nil,
+                }
+            }
+            // #exec
+            19 => {
+                let args = match stack.pop() {
+                    Some(Value::List(l)) => self.lists[l]
+                        .iter()
+                        .map(|x| self.get_value_as_str(x))
+                        .collect::<Vec<String>>(),
+                    _ => panic!("invalid args"),
+                };
+                let cmd = match stack.pop() {
+                    Some(Value::String(cmd)) => self.strings[cmd].clone(),
+                    _ => panic!("invalid args"),
+                };
+                out.flush().unwrap();
+                match Command::new(&cmd).args(&args).output() {
+                    Ok(output) => {
+                        let stdout =
+                            self.new_string(String::from_utf8_lossy(&output.stdout).to_string());
+                        let stderr =
+                            self.new_string(String::from_utf8_lossy(&output.stderr).to_string());
+                        let status = output.status.code().map_or(Value::Nil, Value::Int);
+                        self.new_record(vec![
+                            ("stdout", stdout),
+                            ("stderr", stderr),
+                            ("status", status),
+                        ])
+                    }
+                    // The command could not be started, e.g. because it does
+                    // not exist.
+                    Err(e) => {
+                        let stdout = self.new_string(String::new());
+                        let stderr = self.new_string(format!("{}: {}", cmd, e));
+                        self.new_record(vec![
+                            ("stdout", stdout),
+                            ("stderr", stderr),
+                            ("status", Value::Nil),
+                        ])
+                    }
+                }
+            }
+            _ => panic!("native function {} not found", n),
+        };
+        stack.push(value);
+    }
+}
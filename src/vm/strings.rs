@@ -0,0 +1,82 @@
+use super::{Value, Vm};
+
+impl Vm {
+    /// Runs a built-in method on a string, e.g. `"abc".find("b")`.
+    pub(super) fn string_method(&mut self, s: usize, name: &str, args: &[Value]) -> Value {
+        match (name, args) {
+            ("find", [sub]) => {
+                let sub = self.arg_str(name, sub);
+                match self.strings[s].find(sub) {
+                    Some(i) => Value::Int(i as i32),
+                    None => Value::Nil,
+                }
+            }
+            ("contains", [sub]) => {
+                let sub = self.arg_str(name, sub);
+                Value::Bool(self.strings[s].contains(sub))
+            }
+            ("starts_with", [prefix]) => {
+                let prefix = self.arg_str(name, prefix);
+                Value::Bool(self.strings[s].starts_with(prefix))
+            }
+            ("ends_with", [suffix]) => {
+                let suffix = self.arg_str(name, suffix);
+                Value::Bool(self.strings[s].ends_with(suffix))
+            }
+            ("replace", [from, to]) => {
+                let new_string =
+                    self.strings[s].replace(self.arg_str(name, from), self.arg_str(name, to));
+                self.new_string(new_string)
+            }
+            ("trim", []) => self.new_string(self.strings[s].trim().to_string()),
+            ("trim_start", []) => self.new_string(self.strings[s].trim_start().to_string()),
+            ("trim_end", []) => self.new_string(self.strings[s].trim_end().to_string()),
+            ("upper", []) => self.new_string(self.strings[s].to_uppercase()),
+            ("lower", []) => self.new_string(self.strings[s].to_lowercase()),
+            ("join", [Value::List(l)]) => {
+                let new_string = self.lists[*l]
+                    .iter()
+                    .map(|x| self.get_value_as_str(x))
+                    .collect::<Vec<String>>()
+                    .join(&self.strings[s]);
+                self.new_string(new_string)
+            }
+            // Unlike `#split`, empty parts are kept.
+            ("split", [sep]) => {
+                let parts: Vec<String> = self.strings[s]
+                    .split(self.arg_str(name, sep))
+                    .map(String::from)
+                    .collect();
+                let list = parts.into_iter().map(|p| self.new_string(p)).collect();
+                self.new_list(list)
+            }
+            ("repeat", [Value::Int(n)]) => {
+                self.new_string(self.strings[s].repeat((*n).max(0) as usize))
+            }
+            ("parse_int", []) => match self.strings[s].parse() {
+                Ok(i) => Value::Int(i),
+                Err(_) => Value::Nil,
+            },
+            ("parse_float", []) => match self.strings[s].parse() {
+                Ok(f) => Value::Float(f),
+                Err(_) => Value::Nil,
+            },
+            ("char_code", []) => match self.strings[s].chars().next() {
+                Some(c) => Value::Int(c as i32),
+                None => Value::Nil,
+            },
+            (name, args) => panic!(
+                "no string method named {} taking {} arguments",
+                name,
+                args.len()
+            ),
+        }
+    }
+
+    fn arg_str(&self, name: &str, arg: &Value) -> &str {
+        match arg {
+            Value::String(s) => &self.strings[*s],
+            p => panic!("{} expects a string but got {:?}", name, p),
+        }
+    }
+}
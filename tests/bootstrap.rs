@@ -0,0 +1,8 @@
+use lum::verify_bootstrap;
+
+#[test]
+fn compiler_reaches_fixed_point() {
+    if let Err(e) = verify_bootstrap("compilers/2.l", "lum/compiler/main.lum") {
+        panic!("{}", e);
+    }
+}
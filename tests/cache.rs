@@ -0,0 +1,72 @@
+use std::{fs, path::Path, process::Command};
+
+/// Runs `main.lum` in `dir` with the build cache in `dir/cache`.
+fn run(dir: &Path) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_lum"))
+        .args(["run", dir.join("main.lum").to_str().unwrap()])
+        .env("LUM_CACHE", dir.join("cache"))
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+fn cached_objects(dir: &Path) -> usize {
+    fs::read_dir(dir.join("cache"))
+        .unwrap()
+        .filter(|e| {
+            e.as_ref()
+                .unwrap()
+                .path()
+                .extension()
+                .is_some_and(|e| e == "lo")
+        })
+        .count()
+}
+
+fn append(path: &Path, text: &str) {
+    let mut content = fs::read_to_string(path).unwrap();
+    content.push_str(text);
+    fs::write(path, content).unwrap();
+}
+
+#[test]
+fn only_changed_modules_and_their_dependents_are_recompiled() {
+    let dir = std::env::temp_dir().join(format!("lum_cache_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("modules")).unwrap();
+    fs::copy("tests/lum/modules.lum", dir.join("main.lum")).unwrap();
+    for module in ["util", "geometry", "shapes"] {
+        let file = format!("{}.lum", module);
+        fs::copy(
+            Path::new("tests/lum/modules").join(&file),
+            dir.join("modules").join(&file),
+        )
+        .unwrap();
+    }
+
+    // The main file is always compiled, its three imports are cached.
+    let expected = run(&dir);
+    assert_eq!(cached_objects(&dir), 3);
+    assert_eq!(run(&dir), expected);
+    assert_eq!(cached_objects(&dir), 3);
+
+    // Nothing imports shapes.lum.
+    append(&dir.join("modules/shapes.lum"), "// changed\n");
+    assert_eq!(run(&dir), expected);
+    assert_eq!(cached_objects(&dir), 4);
+
+    // Both other modules import util.lum.
+    append(&dir.join("modules/util.lum"), "#print(\"changed\")\n");
+    let changed = run(&dir);
+    assert_eq!(cached_objects(&dir), 7);
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(
+        changed,
+        expected.replacen("loading util\n", "loading util\nchanged\n", 1)
+    );
+}
@@ -0,0 +1,102 @@
+//! Runs every `.lum` file in `tests/lum` and compares its output with the
+//! `.out` file next to it. If there is an `.in` file it is used as stdin.
+//!
+//! Run `cargo test --test golden -- --bless` to update the `.out` files.
+
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+fn main() {
+    let bless = std::env::args().any(|arg| arg == "--bless");
+
+    let mut files: Vec<PathBuf> = fs::read_dir("tests/lum")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "lum"))
+        .collect();
+    files.sort();
+
+    let mut failed = 0;
+    for file in &files {
+        let actual = run(file);
+        let golden = file.with_extension("out");
+        if bless {
+            fs::write(&golden, &actual).unwrap();
+            println!("blessed {}", golden.display());
+            continue;
+        }
+        match fs::read_to_string(&golden) {
+            Ok(expected) if expected == actual => println!("test {} ... ok", file.display()),
+            Ok(expected) => {
+                println!("test {} ... FAILED", file.display());
+                print_diff(&expected, &actual);
+                failed += 1;
+            }
+            Err(_) => {
+                println!(
+                    "test {} ... FAILED\n  missing {}, run with --bless to create it",
+                    file.display(),
+                    golden.display()
+                );
+                failed += 1;
+            }
+        }
+    }
+
+    println!(
+        "\ntest result: {}. {} passed; {} failed",
+        if failed == 0 { "ok" } else { "FAILED" },
+        files.len() - failed,
+        failed
+    );
+    if failed > 0 {
+        std::process::exit(1);
+    }
+}
+
+/// Runs a program with `lum run`. The output is stdout followed by stderr
+/// and the exit status if it is not zero.
+fn run(file: &Path) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lum"))
+        .arg("run")
+        .arg(file)
+        .args(["first", "second"])
+        .env("LUM_GOLDEN", "golden")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let input = fs::read(file.with_extension("in")).unwrap_or_default();
+    child.stdin.take().unwrap().write_all(&input).unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    let mut res = String::from_utf8_lossy(&output.stdout).to_string();
+    res.push_str(&String::from_utf8_lossy(&output.stderr));
+    match output.status.code() {
+        Some(0) => {}
+        Some(code) => res.push_str(&format!("exit status: {}\n", code)),
+        None => res.push_str("killed by a signal\n"),
+    }
+    res
+}
+
+fn print_diff(expected: &str, actual: &str) {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    for i in 0..expected.len().max(actual.len()) {
+        match (expected.get(i), actual.get(i)) {
+            (Some(e), Some(a)) if e == a => {}
+            (e, a) => {
+                println!("  first difference on line {}:", i + 1);
+                println!("    expected: {}", e.unwrap_or(&"<end of output>"));
+                println!("    actual:   {}", a.unwrap_or(&"<end of output>"));
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,28 @@
+use std::{fs, process::Command};
+
+#[test]
+fn std_and_include_dirs_are_found_from_any_directory() {
+    let dir = std::env::temp_dir().join(format!("lum_imports_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("shared")).unwrap();
+    fs::write(
+        dir.join("shared/greeting.lum"),
+        "class Greeting {\n    static text() { return \"hi\" }\n}\n",
+    )
+    .unwrap();
+    let main = "import { Text } from \"text.lum\"\nimport { Greeting } from \"greeting.lum\"\n#print(Text.pad_left(Greeting.text(), 4, \".\"))\n";
+    fs::write(dir.join("main.lum"), main).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_lum"))
+        .args(["run", "--include", "shared", "main.lum"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "..hi\n");
+}
@@ -0,0 +1,118 @@
+use std::{fs, path::Path, process::Command};
+
+fn lum(args: &[&str]) -> (bool, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_lum"))
+        .args(args)
+        .output()
+        .unwrap();
+    let mut res = String::from_utf8_lossy(&output.stdout).to_string();
+    res.push_str(&String::from_utf8_lossy(&output.stderr));
+    (output.status.success(), res)
+}
+
+fn temp_dir(name: &str) -> String {
+    let dir = std::env::temp_dir().join(format!("lum_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir.to_string_lossy().to_string()
+}
+
+#[test]
+fn linked_objects_run_like_the_program() {
+    let dir = temp_dir("link");
+    let out = format!("{}/modules.l", dir);
+    assert!(lum(&["compile", "tests/lum/modules.lum", &dir]).0);
+
+    let mut objects: Vec<String> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+    objects.sort();
+    assert_eq!(
+        objects,
+        [
+            "000_util.lo",
+            "001_geometry.lo",
+            "002_shapes.lo",
+            "003_modules.lo"
+        ]
+    );
+
+    // The order of the objects does not matter, imported modules run first.
+    let mut args = vec!["link", "-o", &out];
+    let paths: Vec<String> = objects
+        .iter()
+        .rev()
+        .map(|o| format!("{}/{}", dir, o))
+        .collect();
+    args.extend(paths.iter().map(String::as_str));
+    assert!(lum(&args).0);
+
+    let linked = lum(&["run", &out]);
+    let compiled = lum(&["run", "tests/lum/modules.lum"]);
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(linked.0);
+    assert_eq!(linked.1, compiled.1);
+}
+
+#[test]
+fn missing_modules_are_reported() {
+    let dir = temp_dir("link_missing");
+    assert!(lum(&["compile", "tests/lum/modules.lum", &dir]).0);
+    let main = Path::new(&dir).join("003_modules.lo");
+    let out = format!("{}/modules.l", dir);
+
+    let (ok, output) = lum(&["link", "-o", &out, main.to_str().unwrap()]);
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(!ok);
+    assert!(output.contains("modules.lum imports "), "{}", output);
+    assert!(output.contains("which is not linked"), "{}", output);
+}
+
+#[test]
+fn missing_or_corrupt_executables_are_errors() {
+    let dir = temp_dir("corrupt");
+    fs::create_dir_all(&dir).unwrap();
+    let missing = format!("{}/missing.l", dir);
+    let corrupt = format!("{}/corrupt.l", dir);
+    fs::write(&corrupt, "0\nPushInt|nope\n").unwrap();
+
+    let (ok, out) = lum(&["run", &missing]);
+    assert!(!ok);
+    let expected = format!("error: could not read {}", missing);
+    assert!(out.starts_with(&expected), "{}", out);
+    let (ok, out) = lum(&["run", &corrupt]);
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(!ok);
+    let expected = "is not a lum executable: invalid instruction PushInt|nope";
+    assert_eq!(out, format!("error: {} {}\n", corrupt, expected));
+}
+
+#[test]
+fn classes_without_static_columns_are_rejected() {
+    let dir = temp_dir("link_old");
+    assert!(lum(&["compile", "tests/lum/modules.lum", &dir]).0);
+    let util = Path::new(&dir).join("000_util.lo");
+    // Drop the static fields, static methods and required fields.
+    let text: Vec<String> = fs::read_to_string(&util)
+        .unwrap()
+        .lines()
+        .map(|line| {
+            if line.starts_with("class|") {
+                line.split('|').take(7).collect::<Vec<_>>().join("|")
+            } else {
+                line.to_string()
+            }
+        })
+        .collect();
+    fs::write(&util, text.join("\n")).unwrap();
+    let out = format!("{}/modules.l", dir);
+
+    let (ok, output) = lum(&["link", "-o", &out, &dir]);
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(!ok);
+    assert!(
+        output.contains("invalid class Counter|count|next|"),
+        "{}",
+        output
+    );
+}
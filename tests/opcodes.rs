@@ -0,0 +1,87 @@
+use std::{collections::BTreeSet, fs, process::Command};
+
+/// Every instruction the compiler can write. The superinstructions of the
+/// optimizer are only created when an executable is loaded.
+const OPCODES: [&str; 43] = [
+    "PushInt",
+    "PushBool",
+    "PushFloat",
+    "PushNil",
+    "JumpIfFalse",
+    "SetLocal",
+    "GetLocal",
+    "SetGlobal",
+    "GetGlobal",
+    "SetClass",
+    "GetClass",
+    "Jump",
+    "Plus",
+    "Minus",
+    "Multiply",
+    "Divide",
+    "Native",
+    "PushString",
+    "List",
+    "GetField",
+    "SetField",
+    "Instance",
+    "Get",
+    "Set",
+    "Call",
+    "Return",
+    "Pop",
+    "Neg",
+    "Not",
+    "Equals",
+    "NotEquals",
+    "Or",
+    "And",
+    "Less",
+    "LessEqual",
+    "Greater",
+    "GreaterEqual",
+    "IndexGet",
+    "IndexSet",
+    "PushSelf",
+    "Print",
+    "MissingArg",
+    "MissingField",
+];
+
+fn lum(args: &[&str]) {
+    let output = Command::new(env!("CARGO_BIN_EXE_lum"))
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn the_opcodes_golden_test_uses_every_opcode() {
+    let dir = std::env::temp_dir().join(format!("lum_opcodes_{}", std::process::id()));
+    let dir = dir.to_string_lossy().to_string();
+    let _ = fs::remove_dir_all(&dir);
+    let out = format!("{}.l", dir);
+    lum(&["compile", "tests/lum/opcodes.lum", &dir]);
+    lum(&["link", "-o", &out, &dir]);
+    let executable = fs::read_to_string(&out).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    fs::remove_file(&out).unwrap();
+
+    // The string table comes first, then one instruction per line.
+    let mut lines = executable.lines();
+    let strings: usize = lines.next().unwrap().parse().unwrap();
+    let used: BTreeSet<&str> = lines
+        .skip(strings)
+        .map(|line| line.split('|').next().unwrap())
+        .collect();
+    let missing: Vec<&str> = OPCODES
+        .into_iter()
+        .filter(|op| !used.contains(op))
+        .collect();
+    assert!(missing.is_empty(), "not used: {:?}", missing);
+}
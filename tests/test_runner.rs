@@ -0,0 +1,27 @@
+use std::{fs, process::Command};
+
+#[test]
+fn a_test_that_exits_fails_without_ending_the_run() {
+    let dir = std::env::temp_dir().join(format!("lum_test_runner_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let tests =
+        "class ExitTest {\n    test_exits { #exit(2) }\n    test_passes { #assert_eq(1, 1) }\n}\n";
+    fs::write(dir.join("exit_test.lum"), tests).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_lum"))
+        .args(["test", &dir.to_string_lossy()])
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(1), "{}", stdout);
+    assert!(
+        stdout.contains("ExitTest.test_exits ... FAILED"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("exited with status 2"), "{}", stdout);
+    assert!(stdout.contains("ExitTest.test_passes ... ok"), "{}", stdout);
+    assert!(stdout.contains("1 passed; 1 failed"), "{}", stdout);
+}